@@ -1,7 +1,8 @@
 use std::io;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 
+use percent_encoding::percent_decode_str;
 use url::{Host, Url};
 
 use crate::error::{Error, Result};
@@ -40,6 +41,10 @@ impl FromStr for Addr {
 }
 
 impl Addr {
+    pub fn scheme(&self) -> &str {
+        self.url.scheme()
+    }
+
     pub fn is_ssl(&self) -> bool {
         self.url.scheme() == "https"
     }
@@ -95,10 +100,84 @@ impl Addr {
         Ok(vec)
     }
 
+    // Like to_vec, but a domain name is resolved locally and sent as
+    // an IPv4 or IPv6 address.
+    pub fn to_resolved_vec(&self) -> Result<Vec<u8>> {
+        let mut vec = Vec::new();
+        match self.socket_addr()?.ip() {
+            IpAddr::V4(ipv4) => {
+                vec.push(1u8);
+                vec.extend_from_slice(&ipv4.octets());
+            }
+            IpAddr::V6(ipv6) => {
+                vec.push(4u8);
+                vec.extend_from_slice(&ipv6.octets());
+            }
+        }
+        vec.append(&mut self.port());
+        Ok(vec)
+    }
+
+    // Username and password from the URL userinfo, percent-decoded.
+    pub fn username(&self) -> Option<String> {
+        match self.url.username() {
+            "" => None,
+            username => Some(
+                percent_decode_str(username)
+                    .decode_utf8_lossy()
+                    .into_owned(),
+            ),
+        }
+    }
+
+    pub fn password(&self) -> Option<String> {
+        self.url.password().map(|password| {
+            percent_decode_str(password)
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+    }
+
     pub fn path(&self) -> String {
         self.url.path().to_string()
     }
 
+    pub fn request_uri(&self) -> String {
+        match self.url.query() {
+            Some(query) => format!("{}?{}", self.url.path(), query),
+            None => self.url.path().to_string(),
+        }
+    }
+
+    pub fn authority(&self) -> Result<String> {
+        let host = self.url.host_str().ok_or(Error::InvalidHost)?;
+        let port = self.url.port_or_known_default().unwrap_or(80);
+        Ok(format!("{}:{}", host, port))
+    }
+
+    pub fn absolute_uri(&self) -> Result<String> {
+        Ok(format!(
+            "{}://{}{}",
+            self.url.scheme(),
+            self.host_header()?,
+            self.request_uri()
+        ))
+    }
+
+    pub fn host_header(&self) -> Result<String> {
+        let host = self.url.host_str().ok_or(Error::InvalidHost)?;
+        match self.url.port() {
+            Some(port) => Ok(format!("{}:{}", host, port)),
+            None => Ok(host.to_string()),
+        }
+    }
+
+    // Whether both addresses have the same scheme, host and port, so that a
+    // request to one can be sent on a connection to the other.
+    pub fn same_origin(&self, other: &Addr) -> bool {
+        self.url.origin() == other.url.origin()
+    }
+
     pub fn socket_addr(&self) -> Result<SocketAddr> {
         let socket_addrs = self.socket_addrs()?;
         if !socket_addrs.is_empty() {
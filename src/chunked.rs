@@ -0,0 +1,302 @@
+use std::io::{self, BufRead, Read, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::error::{Error, Result};
+use crate::headers::{is_token, Headers};
+use crate::response::{parse_header_line, read_line, MAX_HEAD_LEN};
+
+// ChunkedReader decodes a body sent with the chunked transfer coding
+// (RFC 7230, Section 4.1):
+//
+//	chunked-body   = *chunk
+//	                 last-chunk
+//	                 trailer-part
+//	                 CRLF
+//	chunk          = chunk-size [ chunk-ext ] CRLF
+//	                 chunk-data CRLF
+//	last-chunk     = 1*("0") [ chunk-ext ] CRLF
+//
+// It reads no further than the final CRLF, so the underlying reader can be
+// used for the next message on a persistent connection.
+#[derive(Debug)]
+pub struct ChunkedReader<R> {
+    inner: R,
+    remaining: u64,
+    started: bool,
+    done: bool,
+    extensions: Vec<(String, Option<String>)>,
+    trailers: Headers,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    pub fn new(inner: R) -> Self {
+        ChunkedReader {
+            inner,
+            remaining: 0,
+            started: false,
+            done: false,
+            extensions: Vec::new(),
+            trailers: Headers::new(),
+        }
+    }
+
+    // Extensions of the chunk being read.
+    pub fn extensions(&self) -> &[(String, Option<String>)] {
+        &self.extensions
+    }
+
+    // Trailer fields, available once the body has been read to the end.
+    pub fn trailers(&self) -> &Headers {
+        &self.trailers
+    }
+
+    pub fn into_trailers(self) -> Headers {
+        self.trailers
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn next_chunk(&mut self) -> Result<()> {
+        let mut limit = MAX_HEAD_LEN;
+        if self.started && !read_line(&mut self.inner, &mut limit)?.is_empty() {
+            return Err(Error::InvalidChunk);
+        }
+        self.started = true;
+        let line = read_line(&mut self.inner, &mut limit)?;
+        let (size, extensions) = match line.find(';') {
+            Some(pos) => (&line[..pos], parse_extensions(&line[pos..])?),
+            None => (line.as_str(), Vec::new()),
+        };
+        let size = size.trim();
+        if size.is_empty() || size.len() > 16 || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::InvalidChunk);
+        }
+        self.remaining = u64::from_str_radix(size, 16).map_err(|_| Error::InvalidChunk)?;
+        self.extensions = extensions;
+        if self.remaining == 0 {
+            loop {
+                let line = read_line(&mut self.inner, &mut limit)?;
+                if line.is_empty() {
+                    break;
+                }
+                parse_header_line(&line, &mut self.trailers)?;
+            }
+            self.done = true;
+        }
+        Ok(())
+    }
+
+    fn fill_chunk(&mut self) -> io::Result<()> {
+        if self.remaining == 0 && !self.done {
+            self.next_chunk()?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = {
+            let chunk = self.fill_buf()?;
+            let len = chunk.len().min(buf.len());
+            buf[..len].copy_from_slice(&chunk[..len]);
+            len
+        };
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for ChunkedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.fill_chunk()?;
+        if self.done {
+            return Ok(&[]);
+        }
+        let remaining = self.remaining;
+        let buf = self.inner.fill_buf()?;
+        if buf.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let len = (buf.len() as u64).min(remaining) as usize;
+        Ok(&buf[..len])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = (amt as u64).min(self.remaining);
+        self.inner.consume(amt as usize);
+        self.remaining -= amt;
+    }
+}
+
+// ChunkedWriter encodes every write as one chunk. The body must be ended
+// with finish, which writes the last-chunk and an empty trailer section.
+#[derive(Debug)]
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChunkedWriter { inner }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A zero-size chunk would terminate the body.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:X}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// chunk-ext      = *( BWS ";" BWS chunk-ext-name [ BWS "=" BWS chunk-ext-val ] )
+// chunk-ext-name = token
+// chunk-ext-val  = token / quoted-string
+fn parse_extensions(s: &str) -> Result<Vec<(String, Option<String>)>> {
+    let mut extensions = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(';') => (),
+            None => return Ok(extensions),
+            _ => return Err(Error::InvalidChunk),
+        }
+        skip_whitespace(&mut chars);
+        let name = take_token(&mut chars)?;
+        let mut value = None;
+        skip_whitespace(&mut chars);
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            skip_whitespace(&mut chars);
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                let mut val = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => val.push(chars.next().ok_or(Error::InvalidChunk)?),
+                        Some(c) => val.push(c),
+                        None => return Err(Error::InvalidChunk),
+                    }
+                }
+                value = Some(val);
+            } else {
+                value = Some(take_token(&mut chars)?);
+            }
+        }
+        extensions.push((name, value));
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while matches!(chars.peek(), Some(' ') | Some('\t')) {
+        chars.next();
+    }
+}
+
+// Takes the characters up to the next separator, which must form a token.
+fn take_token(chars: &mut Peekable<Chars>) -> Result<String> {
+    let mut token = String::new();
+    while let Some(c) = chars.peek().copied() {
+        if matches!(c, ' ' | '\t' | ';' | '=') {
+            break;
+        }
+        token.push(c);
+        chars.next();
+    }
+    if is_token(&token) {
+        Ok(token)
+    } else {
+        Err(Error::InvalidChunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunked() {
+        let bytes = b"4\r\nWiki\r\n5;name=value\r\npedia\r\nE; a=\"x;y\"; b\r\n in\r\n\r\nchunks.\r\n0\r\nExpires: never\r\n\r\nnext";
+        let mut reader = &bytes[..];
+        let mut chunked = ChunkedReader::new(&mut reader);
+        let mut body = Vec::new();
+        let mut buf = [0u8; 3];
+        loop {
+            let n = chunked.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            body.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(body, b"Wikipedia in\r\n\r\nchunks.");
+        assert!(chunked.is_done());
+        assert_eq!(chunked.trailers().get("expires"), Some("never"));
+        assert_eq!(reader, b"next");
+    }
+
+    #[test]
+    fn chunked_writer() {
+        let mut writer = ChunkedWriter::new(Vec::new());
+        writer.write_all(b"Wikipedia").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(b" in chunks.").unwrap();
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes, b"9\r\nWikipedia\r\nB\r\n in chunks.\r\n0\r\n\r\n");
+        let mut body = String::new();
+        ChunkedReader::new(&bytes[..])
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "Wikipedia in chunks.");
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(
+            parse_extensions("; a=\"x;\\\"y\" ;b ; c=d").unwrap(),
+            vec![
+                ("a".to_string(), Some("x;\"y".to_string())),
+                ("b".to_string(), None),
+                ("c".to_string(), Some("d".to_string())),
+            ]
+        );
+        assert!(parse_extensions(";").is_err());
+        assert!(parse_extensions("; a=\"open").is_err());
+        assert!(parse_extensions("; a@b").is_err());
+        assert!(parse_extensions("; a=").is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        let mut body = Vec::new();
+        for bytes in &[
+            &b"x\r\n"[..],
+            &b"+3\r\nabc\r\n0\r\n\r\n"[..],
+            &b"3\r\nabcd\r\n0\r\n\r\n"[..],
+            &b"5\r\nabc"[..],
+            &b"11111111111111111\r\n"[..],
+            &b"0\r\nBad Trailer\r\n\r\n"[..],
+        ] {
+            assert!(ChunkedReader::new(*bytes).read_to_end(&mut body).is_err());
+        }
+    }
+}
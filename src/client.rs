@@ -1,10 +1,12 @@
-use std::io;
-
 use url::Url;
 
+use crate::addr::Addr;
 use crate::error::{Error, Result};
-use crate::http::HttpStream;
-use crate::socks::SocksStream;
+use crate::http::{HttpStream, ProxyMode};
+use crate::request::{Method, Request};
+use crate::response::{Response, StreamResponse};
+use crate::socks::{url_auth, Isolation, SocksAddr, SocksAuth, SocksStream};
+use crate::tls::{TlsConfig, TlsInfo};
 
 pub enum Client {
     Http(HttpStream),
@@ -16,13 +18,52 @@ impl Client {
         Ok(Client::Http(HttpStream::connect(target)?))
     }
 
+    pub fn connect_tls(target: &str, tls: &TlsConfig) -> Result<Self> {
+        Ok(Client::Http(HttpStream::connect_tls(target, tls)?))
+    }
+
+    // The proxy scheme selects the protocol: http and https for HTTP proxies,
+    // socks5, socks5h and socks5t for SOCKS5 proxies, socks4 and socks4a for
+    // SOCKS4 proxies. The SOCKS schemes differ in where the target name is
+    // resolved (see socks::Resolve).
     pub fn connect_proxy(proxy_with_scheme: &str, target: &str) -> Result<Self> {
+        Client::connect_proxy_tls(proxy_with_scheme, target, &TlsConfig::default())
+    }
+
+    // The TLS config applies to an https:// target and an https:// proxy.
+    pub fn connect_proxy_tls(
+        proxy_with_scheme: &str,
+        target: &str,
+        tls: &TlsConfig,
+    ) -> Result<Self> {
         let proxy_url = Url::parse(proxy_with_scheme).map_err(Error::UrlParse)?;
         let scheme = proxy_url.scheme();
         if scheme == "http" || scheme == "https" {
-            Client::connect_http(proxy_with_scheme, target)
+            Ok(Client::Http(HttpStream::connect_proxy_tls(
+                proxy_with_scheme,
+                target,
+                ProxyMode::Auto,
+                tls,
+            )?))
         } else if scheme == "socks5" || scheme == "socks5h" || scheme == "socks5t" {
-            Client::connect_socks(proxy_with_scheme, target)
+            let auth = url_auth(&proxy_with_scheme.parse()?);
+            Ok(Client::Socks(SocksStream::connect_auth_tls(
+                proxy_with_scheme,
+                target,
+                &auth,
+                tls,
+            )?))
+        } else if scheme == "socks4" || scheme == "socks4a" {
+            let userid = proxy_with_scheme
+                .parse::<Addr>()?
+                .username()
+                .unwrap_or_default();
+            Ok(Client::Socks(SocksStream::connect_v4_tls(
+                proxy_with_scheme,
+                target,
+                &userid,
+                tls,
+            )?))
         } else {
             Err(Error::UnsupportedProxy)
         }
@@ -32,8 +73,70 @@ impl Client {
         Ok(Client::Http(HttpStream::connect_proxy(proxy, target)?))
     }
 
+    pub fn connect_http_with(proxy: &str, target: &str, mode: ProxyMode) -> Result<Self> {
+        Client::connect_http_tls(proxy, target, mode, &TlsConfig::default())
+    }
+
+    pub fn connect_http_tls(
+        proxy: &str,
+        target: &str,
+        mode: ProxyMode,
+        tls: &TlsConfig,
+    ) -> Result<Self> {
+        Ok(Client::Http(HttpStream::connect_proxy_tls(
+            proxy, target, mode, tls,
+        )?))
+    }
+
+    // Username and password in the proxy URL userinfo are used for the
+    // SOCKS5 username/password authentication.
     pub fn connect_socks(proxy: &str, target: &str) -> Result<Self> {
-        Ok(Client::Socks(SocksStream::connect(proxy, target)?))
+        Client::connect_socks_with(proxy, target, &url_auth(&proxy.parse()?))
+    }
+
+    // The username in the proxy URL userinfo is sent as the SOCKS4 user ID.
+    pub fn connect_socks4(proxy: &str, target: &str) -> Result<Self> {
+        let proxy_addr: Addr = proxy.parse()?;
+        let userid = proxy_addr.username().unwrap_or_default();
+        Ok(Client::Socks(SocksStream::connect_v4(
+            proxy, target, &userid,
+        )?))
+    }
+
+    // Connects through a SOCKS5 proxy offering the methods in auth.
+    pub fn connect_socks_with(proxy: &str, target: &str, auth: &SocksAuth) -> Result<Self> {
+        Client::connect_socks_with_tls(proxy, target, auth, &TlsConfig::default())
+    }
+
+    pub fn connect_socks_with_tls(
+        proxy: &str,
+        target: &str,
+        auth: &SocksAuth,
+        tls: &TlsConfig,
+    ) -> Result<Self> {
+        Ok(Client::Socks(SocksStream::connect_auth_tls(
+            proxy, target, auth, tls,
+        )?))
+    }
+
+    // Connects through a SOCKS5 proxy with credentials generated for the
+    // session, to keep sessions on separate circuits (see socks::Isolation).
+    pub fn connect_socks_isolated(
+        proxy: &str,
+        target: &str,
+        isolation: &Isolation,
+    ) -> Result<Self> {
+        Client::connect_socks_isolated_tls(proxy, target, isolation, &TlsConfig::default())
+    }
+
+    pub fn connect_socks_isolated_tls(
+        proxy: &str,
+        target: &str,
+        isolation: &Isolation,
+        tls: &TlsConfig,
+    ) -> Result<Self> {
+        let auth = isolation.auth(&target.parse()?)?;
+        Client::connect_socks_with_tls(proxy, target, &auth, tls)
     }
 
     pub fn connect_socks_auth(
@@ -47,31 +150,103 @@ impl Client {
         )?))
     }
 
-    pub fn get(&mut self) -> io::Result<Vec<u8>> {
+    pub fn target(&self) -> &Addr {
+        match self {
+            Client::Http(http) => http.target(),
+            Client::Socks(socks) => socks.target(),
+        }
+    }
+
+    // Details of the TLS session to an https:// target.
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        match self {
+            Client::Http(http) => http.tls_info(),
+            Client::Socks(socks) => socks.tls_info(),
+        }
+    }
+
+    // The address bound by a SOCKS proxy, None for HTTP.
+    pub fn bind_addr(&self) -> Option<&SocksAddr> {
+        match self {
+            Client::Http(_) => None,
+            Client::Socks(socks) => Some(socks.bind_addr()),
+        }
+    }
+
+    pub fn send(&mut self, request: Request) -> Result<Response> {
+        match self {
+            Client::Http(http) => http.send(request),
+            Client::Socks(socks) => socks.send(request),
+        }
+    }
+
+    pub fn send_streamed(&mut self, request: Request) -> Result<StreamResponse<'_>> {
+        match self {
+            Client::Http(http) => http.send_streamed(request),
+            Client::Socks(socks) => socks.send_streamed(request),
+        }
+    }
+
+    pub fn request(&mut self, method: Method, body: &[u8]) -> Result<Response> {
+        let mut request = Request::new(method, self.target().clone());
+        request.set_body(body);
+        self.send(request)
+    }
+
+    pub fn get(&mut self) -> Result<Response> {
+        self.request(Method::Get, &[])
+    }
+
+    pub fn head(&mut self) -> Result<Response> {
+        self.request(Method::Head, &[])
+    }
+
+    pub fn post(&mut self, body: &[u8]) -> Result<Response> {
+        self.request(Method::Post, body)
+    }
+
+    pub fn post_json(&mut self, body: &str) -> Result<Response> {
         match self {
-            Client::Http(http) => http.get(),
-            Client::Socks(socks) => socks.get(),
+            Client::Http(http) => http.post_json(body),
+            Client::Socks(socks) => socks.post_json(body),
         }
     }
+
+    pub fn put(&mut self, body: &[u8]) -> Result<Response> {
+        self.request(Method::Put, body)
+    }
+
+    pub fn patch(&mut self, body: &[u8]) -> Result<Response> {
+        self.request(Method::Patch, body)
+    }
+
+    pub fn delete(&mut self) -> Result<Response> {
+        self.request(Method::Delete, &[])
+    }
+
+    pub fn options(&mut self) -> Result<Response> {
+        self.request(Method::Options, &[])
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{Read, Write};
 
     #[test]
     fn client_http() {
         let mut client = Client::connect("http://api.ipify.org").unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
 
     #[test]
     fn client_https() {
         let mut client = Client::connect("https://api.ipify.org").unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
 
@@ -79,8 +254,8 @@ mod tests {
     fn client_http_proxy() {
         let mut client =
             Client::connect_proxy("http://127.0.0.1:5858", "https://api.ipify.org").unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
 
@@ -88,8 +263,8 @@ mod tests {
     fn client_socks() {
         let mut client =
             Client::connect_proxy("socks5://127.0.0.1:5959", "https://api.ipify.org").unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
 
@@ -98,8 +273,8 @@ mod tests {
         let mut client =
             Client::connect_socks_auth("127.0.0.1:5757", "https://api.ipify.org", "test", "tset")
                 .unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
 
@@ -109,4 +284,25 @@ mod tests {
             Client::connect_socks_auth("127.0.0.1:5757", "https://api.ipify.org", "test", "test");
         assert!(client.is_err());
     }
+
+    #[test]
+    fn client_socks_url_auth() {
+        let (addr, handle) = crate::mock_server(1, |mut stream| {
+            let mut greeting = [0u8; 4];
+            stream.read_exact(&mut greeting).unwrap();
+            stream.write_all(&[5, 2]).unwrap();
+            let mut auth = [0u8; 11];
+            stream.read_exact(&mut auth).unwrap();
+            stream.write_all(&[1, 1]).unwrap();
+            (greeting, auth)
+        });
+        let proxy = format!("socks5h://us%3Ar:p%40ss@{}", addr);
+        match Client::connect_proxy(&proxy, "http://example.org") {
+            Err(Error::AuthFailure) => (),
+            _ => panic!("expected authentication failure"),
+        }
+        let (greeting, auth) = handle.join().unwrap().remove(0);
+        assert_eq!(greeting, [5, 2, 2, 0]);
+        assert_eq!(&auth, b"\x01\x04us:r\x04p@ss");
+    }
 }
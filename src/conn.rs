@@ -0,0 +1,117 @@
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::addr::Addr;
+use crate::error::{Error, Result};
+use crate::request::{Method, Request};
+use crate::response::{Response, StreamResponse, MAX_HEAD_LEN};
+use crate::stream::Stream;
+use crate::tls::TlsInfo;
+
+// Conn is a persistent HTTP/1.1 connection to one target, made directly or
+// through a proxy. HttpStream and SocksStream send their requests with it.
+#[derive(Debug)]
+pub(crate) struct Conn {
+    stream: BufReader<Stream>,
+    target: Addr,
+    // Absolute-form requests go to an HTTP proxy that forwards them, with
+    // the Proxy-Authorization header unless the request has its own.
+    absolute_form: bool,
+    proxy_auth: Option<String>,
+    // Broken is set when an exchange was not completed, so the connection
+    // is no longer at the start of a response.
+    broken: bool,
+}
+
+impl Conn {
+    pub(crate) fn new(stream: Stream, target: Addr) -> Self {
+        Conn {
+            stream: BufReader::new(stream),
+            target,
+            absolute_form: false,
+            proxy_auth: None,
+            broken: false,
+        }
+    }
+
+    // Sends the requests in absolute form to a forwarding proxy.
+    pub(crate) fn forwarded(mut self, proxy_auth: Option<String>) -> Self {
+        self.absolute_form = true;
+        self.proxy_auth = proxy_auth;
+        self
+    }
+
+    pub(crate) fn target(&self) -> &Addr {
+        &self.target
+    }
+
+    #[cfg(test)]
+    pub(crate) fn get_mut(&mut self) -> &mut BufReader<Stream> {
+        &mut self.stream
+    }
+
+    // Details of the TLS session to an https:// target. None when requests
+    // are forwarded by the proxy, which runs the session to the target.
+    pub(crate) fn tls_info(&self) -> Option<TlsInfo> {
+        if self.absolute_form || !self.target.is_ssl() {
+            return None;
+        }
+        self.stream.get_ref().tls_info()
+    }
+
+    pub(crate) fn send(&mut self, request: Request) -> Result<Response> {
+        self.send_streamed(request)?.into_buffered()
+    }
+
+    // Sends the request and returns as soon as the response head is read.
+    // The body has to be read to the end before the next request is sent,
+    // otherwise the next request fails.
+    pub(crate) fn send_streamed(&mut self, mut request: Request) -> Result<StreamResponse<'_>> {
+        if !request.target().same_origin(&self.target) {
+            return Err(Error::TargetMismatch);
+        }
+        if self.broken {
+            return Err(Error::BrokenConnection);
+        }
+        if self.absolute_form {
+            request.set_absolute_form(true);
+            if let Some(auth) = &self.proxy_auth {
+                if !request.headers().contains("Proxy-Authorization") {
+                    request.headers_mut().insert("Proxy-Authorization", auth)?;
+                }
+            }
+        }
+        // A failure before the response head is read leaves the connection
+        // in the middle of the exchange.
+        self.broken = true;
+        let mut writer = BufWriter::new(self.stream.get_mut());
+        request.write_to(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        let mut response =
+            StreamResponse::read_from_conn(&mut self.stream, &mut self.broken, request.method())?;
+        // Only the proxy answers 407 (Proxy Authentication Required). Its
+        // body is skipped to keep the connection usable, unless it is longer
+        // than a response head, which leaves the connection broken.
+        if self.absolute_form && response.status_code() == 407 {
+            let _ = io::copy(
+                &mut response.body().take(MAX_HEAD_LEN as u64),
+                &mut io::sink(),
+            );
+            return Err(Error::ProxyAuthRequired);
+        }
+        Ok(response)
+    }
+
+    pub(crate) fn get(&mut self) -> Result<Response> {
+        self.send(Request::new(Method::Get, self.target.clone()))
+    }
+
+    pub(crate) fn post_json(&mut self, body: &str) -> Result<Response> {
+        let mut request = Request::new(Method::Post, self.target.clone());
+        request
+            .headers_mut()
+            .insert("Content-Type", "application/json")?;
+        request.set_body(body);
+        self.send(request)
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use failure::Fail;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -20,10 +22,30 @@ pub enum Error {
     AuthFailure,
     #[fail(display = "Wrong http")]
     WrongHttp,
-    #[fail(display = "{}", _0)]
-    NativeTls(#[cause] native_tls::HandshakeError<std::net::TcpStream>),
-    #[fail(display = "{}", _0)]
-    TlsConnector(#[cause] native_tls::Error),
+    #[fail(display = "Invalid status line")]
+    InvalidStatusLine,
+    #[fail(display = "Invalid header line")]
+    InvalidHeader,
+    #[fail(display = "Invalid method")]
+    InvalidMethod,
+    #[fail(display = "Invalid content length")]
+    InvalidContentLength,
+    #[fail(display = "Invalid transfer encoding")]
+    InvalidTransferEncoding,
+    #[fail(display = "Invalid chunk")]
+    InvalidChunk,
+    #[fail(display = "Request target does not match the connected host")]
+    TargetMismatch,
+    #[fail(display = "Connection is unusable after an unfinished exchange")]
+    BrokenConnection,
+    #[fail(display = "TLS: {}", _0)]
+    Tls(#[cause] TlsError),
+    #[fail(display = "TLS: peer certificate does not match the pinned hashes")]
+    PinMismatch,
+    #[fail(display = "TLS: pins and client certificates need a TLS session to the target")]
+    TlsRequiresTunnel,
+    #[fail(display = "TLS: unsupported ALPN protocol {}", _0)]
+    UnsupportedAlpn(String),
     #[fail(display = "Invalid address type")]
     InvalidAddressType,
     #[fail(display = "Invalid reserved byte")]
@@ -44,6 +66,12 @@ pub enum Error {
     InvalidRuleset,
     #[fail(display = "General failure")]
     GeneralFailure,
+    #[fail(display = "Request rejected or failed")]
+    RequestRejected,
+    #[fail(display = "Request failed, client is not running identd or it is not reachable")]
+    IdentdUnreachable,
+    #[fail(display = "Request failed, identd could not confirm the user ID")]
+    IdentdMismatch,
     // #[fail(display = "Target address is invalid: {}", _0)]
     // InvalidTargetAddress(&'static str),
     #[fail(display = "Url: is empty")]
@@ -96,6 +124,60 @@ pub enum Error {
     EmptyVec,
     #[fail(display = "Unsupported proxy")]
     UnsupportedProxy,
+    #[fail(display = "Proxy: tunnel to target forbidden")]
+    ProxyForbidden,
+    #[fail(display = "Proxy: authentication required")]
+    ProxyAuthRequired,
+    #[fail(display = "Proxy: bad gateway")]
+    ProxyBadGateway,
+    #[fail(display = "Proxy: unexpected status {}", _0)]
+    ProxyStatus(u16),
+}
+
+// TlsError is a failure of the TLS backend, which is kept as the source.
+#[derive(Debug)]
+pub struct TlsError {
+    kind: TlsErrorKind,
+    source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsErrorKind {
+    // The config has an invalid certificate, key or setting.
+    Config,
+    // The peer certificate did not verify. native-tls does not tell these
+    // apart and reports them as Handshake.
+    Certificate,
+    // The handshake failed for another reason.
+    Handshake,
+}
+
+impl TlsError {
+    pub(crate) fn new<E>(kind: TlsErrorKind, source: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        TlsError {
+            kind,
+            source: source.into(),
+        }
+    }
+
+    pub fn kind(&self) -> TlsErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl std::error::Error for TlsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.source)
+    }
 }
 
 impl From<std::io::Error> for Error {
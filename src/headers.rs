@@ -0,0 +1,141 @@
+use std::fmt;
+
+use crate::error::{Error, Result};
+
+// Header contains the header fields of a request or a response.
+// HTTP defines that header names are case-insensitive, so lookups
+// ignore case, while the original spelling and order of the fields
+// are kept for writing them back to the wire.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headers {
+    inner: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.inner
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.inner
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.inner
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+
+    pub fn insert(&mut self, name: &str, value: &str) -> Result<()> {
+        check_field(name, value)?;
+        self.remove(name);
+        self.inner.push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    pub fn append(&mut self, name: &str, value: &str) -> Result<()> {
+        check_field(name, value)?;
+        self.inner.push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.inner
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.inner
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+// token = 1*tchar (RFC 9110, Section 5.6.2), used for field names and
+// methods.
+pub(crate) fn is_token(s: &str) -> bool {
+    let is_tchar = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    !s.is_empty() && s.chars().all(is_tchar)
+}
+
+// A field value must not contain CR, LF or NUL (RFC 9110, Section 5.5),
+// which would end the field early and let the rest of the value inject
+// other fields or a whole request.
+pub(crate) fn is_field_value(s: &str) -> bool {
+    !s.contains(&['\r', '\n', '\0'][..])
+}
+
+fn check_field(name: &str, value: &str) -> Result<()> {
+    if is_token(name) && is_field_value(value) {
+        Ok(())
+    } else {
+        Err(Error::InvalidHeader)
+    }
+}
+
+impl fmt::Display for Headers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, value) in self.iter() {
+            write!(f, "{}: {}\r\n", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_insensitive() {
+        let mut headers = Headers::new();
+        headers.append("Content-Type", "text/plain").unwrap();
+        headers.append("set-cookie", "a=1").unwrap();
+        headers.append("Set-Cookie", "b=2").unwrap();
+        assert_eq!(headers.get("content-type"), Some("text/plain"));
+        assert_eq!(headers.get_all("SET-COOKIE"), vec!["a=1", "b=2"]);
+        headers.insert("SET-COOKIE", "c=3").unwrap();
+        assert_eq!(headers.get_all("set-cookie"), vec!["c=3"]);
+        assert_eq!(
+            headers.to_string(),
+            "Content-Type: text/plain\r\nSET-COOKIE: c=3\r\n"
+        );
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let mut headers = Headers::new();
+        headers.append("X-Ok", "a\tb").unwrap();
+        for (name, value) in &[
+            ("X", "a\r\nEvil: 1"),
+            ("X", "a\nb"),
+            ("X", "a\0b"),
+            ("X Y", "a"),
+            ("X:", "a"),
+            ("", "a"),
+        ] {
+            assert!(headers.append(name, value).is_err());
+            assert!(headers.insert(name, value).is_err());
+        }
+        assert_eq!(headers.to_string(), "X-Ok: a\tb\r\n");
+    }
+}
@@ -1,93 +1,180 @@
-use std::io::{self, Read, Write};
+use std::io::{Read, Write};
 use std::net::TcpStream;
 
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
 use crate::addr::Addr;
+use crate::conn::Conn;
 use crate::error::{Error, Result};
+use crate::request::{Method, Request};
+use crate::response::{Response, StreamResponse, MAX_HEAD_LEN};
 use crate::stream::Stream;
+use crate::tls::{TlsConfig, TlsInfo};
+
+// ProxyMode selects how requests reach the target through an HTTP proxy.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ProxyMode {
+    // CONNECT tunnel for https:// targets, absolute-form requests for
+    // http:// targets.
+    #[default]
+    Auto,
+    // CONNECT tunnel for every target.
+    Tunnel,
+    // Absolute-form requests for every target. The proxy itself talks TLS
+    // to https:// targets, so it sees the plain requests.
+    Forward,
+}
 
 pub struct HttpStream {
-    stream: Stream,
-    target: Addr,
-    // is_proxy: bool,
+    conn: Conn,
     // bind_addr: Host,
     // bind_port: [u8; 2],
 }
 
 impl HttpStream {
     pub fn connect(target: &str) -> Result<Self> {
+        HttpStream::connect_tls(target, &TlsConfig::default())
+    }
+
+    pub fn connect_tls(target: &str, tls: &TlsConfig) -> Result<Self> {
         let target: Addr = target.parse()?;
+        tls.check_target(target.is_ssl())?;
         let stream = TcpStream::connect(target.socket_addr()?)?;
+        let stream = Stream::new_tcp(stream);
         let stream = if target.is_ssl() {
-            Stream::new_tls(&target.host()?, stream)?
+            Stream::new_tls_with(&target.host()?, stream, tls)?
         } else {
-            Stream::new_tcp(stream)
+            stream
         };
         Ok(HttpStream {
-            stream,
-            target,
-            // is_proxy: true,
+            conn: Conn::new(stream, target),
         })
     }
 
     pub fn connect_proxy(proxy: &str, target: &str) -> Result<Self> {
-        let target: Addr = target.parse()?;
-        let proxy_addr: Addr = proxy.parse()?;
-        let stream = TcpStream::connect(proxy_addr.socket_addr()?)?;
-        // let stream = if proxy_addr.is_ssl() {
-        //     Stream::new_tls(&proxy_addr.host()?, stream)?
-        // } else {
-        //     Stream::new_tcp(stream)
-        // };
-        let stream = Stream::new_tcp(stream);
-        Ok(HttpStream {
-            stream,
-            target,
-            // is_proxy: true,
-        })
+        HttpStream::connect_proxy_with(proxy, target, ProxyMode::Auto)
     }
 
-    pub fn get(&mut self) -> io::Result<Vec<u8>> {
-        let request = format!(
-            "GET {} HTTP/1.0\r\nHost: {}\r\n\r\n",
-            self.target.path(),
-            self.target.host()?
-        )
-        .into_bytes();
-        self.stream.write_all(&request)?;
-        self.stream.flush()?;
-        let mut response = vec![];
-        self.stream.read_to_end(&mut response)?;
-        let pos = response
-            .windows(4)
-            .position(|x| x == b"\r\n\r\n")
-            .ok_or_else(|| Error::WrongHttp)?;
-        let body = &response[pos + 4..response.len()];
-        Ok(body.to_vec())
-    }
-
-    pub fn post_json(&mut self, body: &str) -> io::Result<Vec<u8>> {
-        let body = if !body.is_empty() {
-            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    pub fn connect_proxy_with(proxy: &str, target: &str, mode: ProxyMode) -> Result<Self> {
+        HttpStream::connect_proxy_tls(proxy, target, mode, &TlsConfig::default())
+    }
+
+    // The TLS config applies both to an https:// proxy and to the target,
+    // except for the pins and the client certificate, which apply to the
+    // target only. They fail with TlsRequiresTunnel when there is no TLS
+    // session to the target: for http:// targets and in Forward mode.
+    pub fn connect_proxy_tls(
+        proxy: &str,
+        target: &str,
+        mode: ProxyMode,
+        tls: &TlsConfig,
+    ) -> Result<Self> {
+        let target: Addr = target.parse()?;
+        let proxy_addr: Addr = proxy.parse()?;
+        let is_tunnel = match mode {
+            ProxyMode::Auto => target.is_ssl(),
+            ProxyMode::Tunnel => true,
+            ProxyMode::Forward => false,
+        };
+        tls.check_target(is_tunnel && target.is_ssl())?;
+        let stream = Stream::new_tcp(TcpStream::connect(proxy_addr.socket_addr()?)?);
+        // An https:// proxy is reached over TLS. Requests to https:// targets
+        // then run a second TLS session inside the first one.
+        let mut stream = if proxy_addr.is_ssl() {
+            Stream::new_tls_with(&proxy_addr.host()?, stream, &tls.for_proxy())?
+        } else {
+            stream
+        };
+        let proxy_auth = basic_auth(&proxy_addr);
+        if is_tunnel {
+            tunnel(&mut stream, &target, proxy_auth.as_deref())?;
+        }
+        let stream = if is_tunnel && target.is_ssl() {
+            Stream::new_tls_with(&target.host()?, stream, tls)?
         } else {
-            String::new()
+            stream
         };
-        let request = format!(
-            "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\n{}\r\n",
-            self.target.path(),
-            self.target.host()?,
-            body
-        )
-        .into_bytes();
-        self.stream.write_all(&request)?;
-        self.stream.flush()?;
-        let mut response = vec![];
-        self.stream.read_to_end(&mut response)?;
-        let pos = response
-            .windows(4)
-            .position(|x| x == b"\r\n\r\n")
-            .ok_or_else(|| Error::WrongHttp)?;
-        let body = &response[pos + 4..response.len()];
-        Ok(body.to_vec())
+        let conn = Conn::new(stream, target);
+        let conn = if is_tunnel {
+            conn
+        } else {
+            conn.forwarded(proxy_auth)
+        };
+        Ok(HttpStream { conn })
+    }
+
+    pub fn target(&self) -> &Addr {
+        self.conn.target()
+    }
+
+    // Details of the TLS session to an https:// target. None when requests
+    // are forwarded by the proxy, which runs the session to the target.
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        self.conn.tls_info()
+    }
+
+    pub fn send(&mut self, request: Request) -> Result<Response> {
+        self.conn.send(request)
+    }
+
+    // Sends the request and returns as soon as the response head is read.
+    // The body has to be read to the end before the next request is sent,
+    // otherwise the next request fails.
+    pub fn send_streamed(&mut self, request: Request) -> Result<StreamResponse<'_>> {
+        self.conn.send_streamed(request)
+    }
+
+    pub fn get(&mut self) -> Result<Response> {
+        self.conn.get()
+    }
+
+    pub fn post_json(&mut self, body: &str) -> Result<Response> {
+        self.conn.post_json(body)
+    }
+}
+
+// Credentials from the proxy URL userinfo for the Basic authentication
+// scheme (RFC 7617), sent in the Proxy-Authorization header.
+fn basic_auth(proxy: &Addr) -> Option<String> {
+    let username = proxy.username()?;
+    let password = proxy.password().unwrap_or_default();
+    let credentials = STANDARD.encode(format!("{}:{}", username, password));
+    Some(format!("Basic {}", credentials))
+}
+
+// The proxy response head to CONNECT is read byte by byte, so that nothing
+// the target sends after it is lost before the TLS handshake.
+fn read_head<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD_LEN {
+            return Err(Error::WrongHttp);
+        }
+        reader.read_exact(&mut byte)?;
+        head.push(byte[0]);
+    }
+    Ok(head)
+}
+
+// Asks the proxy to open a tunnel to the target with CONNECT
+// (RFC 7231, Section 4.3.6).
+fn tunnel<S: Read + Write>(stream: &mut S, target: &Addr, auth: Option<&str>) -> Result<()> {
+    let mut request = Request::new(Method::Connect, target.clone());
+    if let Some(auth) = auth {
+        request.headers_mut().insert("Proxy-Authorization", auth)?;
+    }
+    request.write_to(stream)?;
+    stream.flush()?;
+    let head = read_head(stream)?;
+    let response = Response::read_streamed(&head[..], &Method::Connect)?;
+    match response.status_code() {
+        200..=299 => Ok(()),
+        403 => Err(Error::ProxyForbidden),
+        407 => Err(Error::ProxyAuthRequired),
+        502 => Err(Error::ProxyBadGateway),
+        status => Err(Error::ProxyStatus(status)),
     }
 }
 
@@ -114,16 +201,16 @@ mod tests {
     #[test]
     fn http() {
         let mut client = HttpStream::connect("http://api.ipify.org").unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
 
     #[test]
     fn https() {
         let mut client = HttpStream::connect("https://api.ipify.org").unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
 
@@ -131,8 +218,244 @@ mod tests {
     fn http_proxy() {
         let mut client =
             HttpStream::connect_proxy("127.0.0.1:5858", "https://api.ipify.org").unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
+
+    // Reads a request head as sent by the client.
+    fn request_head(stream: &mut TcpStream) -> String {
+        String::from_utf8(read_head(stream).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn http_keep_alive() {
+        let (addr, _) = crate::mock_server(1, |mut stream| {
+            for body in &["first", "second"] {
+                request_head(&mut stream);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        let mut client = HttpStream::connect(&format!("http://{}", addr)).unwrap();
+        assert_eq!(client.get().unwrap().body(), b"first");
+        assert_eq!(client.get().unwrap().body(), b"second");
+    }
+
+    #[test]
+    fn http_unfinished_body() {
+        let (addr, _) = crate::mock_server(1, |mut stream| {
+            for response in &[&b"5\r\n\r\nfirst"[..], &b"11\r\n\r\nsecond body"[..]] {
+                request_head(&mut stream);
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: ")
+                    .unwrap();
+                stream.write_all(response).unwrap();
+            }
+        });
+        let mut client = HttpStream::connect(&format!("http://{}", addr)).unwrap();
+        let target = client.target().clone();
+        let mut response = client
+            .send_streamed(Request::new(Method::Get, target.clone()))
+            .unwrap();
+        response.body().read_to_end(&mut Vec::new()).unwrap();
+        drop(response);
+        let mut response = client
+            .send_streamed(Request::new(Method::Get, target.clone()))
+            .unwrap();
+        let mut buf = [0u8; 6];
+        response.body().read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"second");
+        drop(response);
+        match client.send(Request::new(Method::Get, target)) {
+            Err(Error::BrokenConnection) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn http_target_mismatch() {
+        let (addr, handle) = crate::mock_server(1, |mut stream| {
+            let request = request_head(&mut stream);
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .unwrap();
+            request
+        });
+        let target = format!("http://{}", addr);
+        let mut client = HttpStream::connect(&target).unwrap();
+        let other = Request::new(Method::Get, "http://example.org/".parse().unwrap());
+        match client.send(other) {
+            Err(Error::TargetMismatch) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        let same = Request::new(Method::Get, format!("{}/other", target).parse().unwrap());
+        assert_eq!(client.send(same).unwrap().status_code(), 204);
+        assert!(handle.join().unwrap()[0].starts_with("GET /other HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn http_proxy_connect_refused() {
+        for (status, reason) in &[
+            (403, "Forbidden"),
+            (407, "Proxy Authentication Required"),
+            (502, "Bad Gateway"),
+            (503, "Service Unavailable"),
+        ] {
+            let (addr, handle) = crate::mock_server(1, move |mut stream| {
+                let request = request_head(&mut stream);
+                write!(
+                    stream,
+                    "HTTP/1.1 {} {}\r\nContent-Length: 0\r\n\r\n",
+                    status, reason
+                )
+                .unwrap();
+                request
+            });
+            let result = HttpStream::connect_proxy(&addr, "https://example.org");
+            let request = handle.join().unwrap().remove(0);
+            assert!(request
+                .starts_with("CONNECT example.org:443 HTTP/1.1\r\nHost: example.org:443\r\n"));
+            match (status, result) {
+                (403, Err(Error::ProxyForbidden)) => (),
+                (407, Err(Error::ProxyAuthRequired)) => (),
+                (502, Err(Error::ProxyBadGateway)) => (),
+                (503, Err(Error::ProxyStatus(503))) => (),
+                (_, result) => panic!("unexpected result {:?}", result.err()),
+            }
+        }
+    }
+
+    #[test]
+    fn http_target_tls() {
+        let tls = TlsConfig::new();
+        for tls in &[
+            tls.clone().pin_spki_sha256([0u8; 32]),
+            tls.identity_pem(b"cert", b"key"),
+        ] {
+            for (mode, target) in &[
+                (ProxyMode::Forward, "https://example.org/"),
+                (ProxyMode::Auto, "http://example.org/"),
+                (ProxyMode::Tunnel, "http://example.org/"),
+            ] {
+                match HttpStream::connect_proxy_tls("http://127.0.0.1:1", target, *mode, tls) {
+                    Err(Error::TlsRequiresTunnel) => (),
+                    result => panic!("unexpected result {:?}", result.err()),
+                }
+            }
+            match HttpStream::connect_tls("http://127.0.0.1:1/", tls) {
+                Err(Error::TlsRequiresTunnel) => (),
+                result => panic!("unexpected result {:?}", result.err()),
+            }
+        }
+    }
+
+    #[test]
+    fn http_proxy_modes() {
+        for (mode, target, expected) in &[
+            (
+                ProxyMode::Auto,
+                "http://example.org/a?b",
+                "GET http://example.org/a?b HTTP/1.1\r\nHost: example.org\r\n\r\n",
+            ),
+            (
+                ProxyMode::Forward,
+                "https://example.org/",
+                "GET https://example.org/ HTTP/1.1\r\nHost: example.org\r\n\r\n",
+            ),
+            (
+                ProxyMode::Tunnel,
+                "http://example.org/a?b",
+                "CONNECT example.org:80 HTTP/1.1\r\nHost: example.org:80\r\n\r\n\
+                 GET /a?b HTTP/1.1\r\nHost: example.org\r\n\r\n",
+            ),
+        ] {
+            let (addr, handle) = crate::mock_server(1, |mut stream| {
+                let mut request = request_head(&mut stream);
+                if request.starts_with("CONNECT") {
+                    stream
+                        .write_all(b"HTTP/1.1 200 Established\r\n\r\n")
+                        .unwrap();
+                    request.push_str(&request_head(&mut stream));
+                }
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .unwrap();
+                request
+            });
+            let mut client = HttpStream::connect_proxy_with(&addr, target, *mode).unwrap();
+            assert_eq!(client.get().unwrap().body(), b"ok");
+            assert_eq!(&handle.join().unwrap()[0], expected);
+        }
+    }
+
+    #[test]
+    fn http_proxy_auth_body() {
+        let (addr, handle) = crate::mock_server(1, |mut stream| {
+            request_head(&mut stream);
+            stream
+                .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 6\r\n\r\ndenied")
+                .unwrap();
+            let request = request_head(&mut stream);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .unwrap();
+            request
+        });
+        let mut client = HttpStream::connect_proxy(&addr, "http://example.org").unwrap();
+        match client.get() {
+            Err(Error::ProxyAuthRequired) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(client.get().unwrap().body(), b"ok");
+        assert!(handle.join().unwrap()[0].starts_with("GET http://example.org/ HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn https_proxy_handshake() {
+        let (addr, handle) = crate::mock_server(1, |mut stream| {
+            let mut record = [0u8; 1];
+            stream.read_exact(&mut record).unwrap();
+            record[0]
+        });
+        let proxy = format!("https://{}", addr);
+        assert!(HttpStream::connect_proxy(&proxy, "https://example.org").is_err());
+        // The first byte sent to the proxy opens a TLS handshake record.
+        assert_eq!(handle.join().unwrap(), [0x16]);
+    }
+
+    #[test]
+    fn http_proxy_auth() {
+        for (mode, expected) in &[
+            (
+                ProxyMode::Auto,
+                "GET http://example.org/ HTTP/1.1\r\nHost: example.org\r\nProxy-Authorization: Basic dXNlcjpwQHNz\r\n\r\n",
+            ),
+            (
+                ProxyMode::Tunnel,
+                "CONNECT example.org:80 HTTP/1.1\r\nHost: example.org:80\r\nProxy-Authorization: Basic dXNlcjpwQHNz\r\n\r\n",
+            ),
+        ] {
+            let (addr, handle) = crate::mock_server(1, |mut stream| {
+                let request = request_head(&mut stream);
+                stream
+                    .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 0\r\n\r\n")
+                    .unwrap();
+                request
+            });
+            let proxy = format!("http://user:p%40ss@{}", addr);
+            let result = HttpStream::connect_proxy_with(&proxy, "http://example.org", *mode)
+                .and_then(|mut client| client.get());
+            match result {
+                Err(Error::ProxyAuthRequired) => (),
+                result => panic!("unexpected result {:?}", result),
+            }
+            assert_eq!(&handle.join().unwrap()[0], expected);
+        }
+    }
 }
@@ -1,9 +1,18 @@
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("one of the features `native-tls` or `rustls` is required");
+
 pub mod addr;
+pub mod chunked;
 pub mod client;
+mod conn;
 pub mod error;
+pub mod headers;
 pub mod http;
+pub mod request;
+pub mod response;
 pub mod socks;
 pub mod stream;
+pub mod tls;
 
 #[cfg(test)]
 #[macro_use]
@@ -26,6 +35,27 @@ pub(crate) fn my_ip() -> String {
     split[1].to_string()
 }
 
+// Serves the given number of connections on a local port, one after the
+// other, and returns the address and the results of the handler.
+#[cfg(test)]
+pub(crate) fn mock_server<T, F>(
+    connections: usize,
+    mut handler: F,
+) -> (String, std::thread::JoinHandle<Vec<T>>)
+where
+    T: Send + 'static,
+    F: FnMut(std::net::TcpStream) -> T + Send + 'static,
+{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let handle = std::thread::spawn(move || {
+        (0..connections)
+            .map(|_| handler(listener.accept().unwrap().0))
+            .collect()
+    });
+    (addr, handle)
+}
+
 #[cfg(test)]
 mod tests {
     lazy_static! {
@@ -1,210 +1,552 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use crate::addr::Addr;
+use crate::chunked::ChunkedWriter;
+use crate::error::{Error, Result};
+use crate::headers::{is_field_value, is_token, Headers};
+use crate::response::{content_length, is_chunked};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    Custom(String),
+}
+
+impl FromStr for Method {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // method = token, and methods are case-sensitive (RFC 7230, Section 3.1.1)
+        if !is_token(s) {
+            return Err(Error::InvalidMethod);
+        }
+        Ok(match s {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            _ => Method::Custom(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let method = match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Custom(method) => method,
+        };
+        write!(f, "{}", method)
+    }
+}
+
+pub enum Body {
+    Bytes(Vec<u8>),
+    // Reader streams the body from any reader. With a known length it is
+    // sent with Content-Length, otherwise with the chunked transfer coding.
+    Reader(Box<dyn Read + Send>, Option<u64>),
+}
+
+impl Body {
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::Reader(_, len) => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Body::Bytes(Vec::new())
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => f.debug_tuple("Bytes").field(bytes).finish(),
+            Body::Reader(_, len) => f.debug_tuple("Reader").field(len).finish(),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for Body {
+    fn from(bytes: &[u8]) -> Body {
+        Body::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<String> for Body {
+    fn from(s: String) -> Body {
+        Body::Bytes(s.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(s: &str) -> Body {
+        Body::Bytes(s.as_bytes().to_vec())
+    }
+}
+
+#[derive(Debug)]
 pub struct Request {
     // Method specifies the HTTP method (GET, POST, PUT, etc.).
-    // For client requests, an empty string means GET.
-    pub method: String,
-
-    // URL specifies either the URI being requested (for server
-    // requests) or the URL to access (for client requests).
-    //
-    // For server requests, the URL is parsed from the URI
-    // supplied on the Request-Line as stored in RequestURI.  For
-    // most requests, fields other than Path and RawQuery will be
-    // empty. (See RFC 7230, Section 5.3)
-    //
-    // For client requests, the URL's Host specifies the server to
-    // connect to, while the Request's Host field optionally
-    // specifies the Host header value to send in the HTTP
-    // request.
-    // pub URL: URL,
-
-    // The protocol version for incoming server requests.
-    //
-    // For client requests, these fields are ignored. The HTTP
-    // client code always uses either HTTP/1.1 or HTTP/2.
-    // See the docs on Transport for details.
-    pub proto: String,   // "HTTP/1.0"
-    pub proto_major: u8, // 1
-    pub proto_minor: u8, // 0
-
-    // Header contains the request header fields either received
-    // by the server or to be sent by the client.
-    //
-    // If a server received a request with header lines,
-    //
-    //	Host: example.com
-    //	accept-encoding: gzip, deflate
-    //	Accept-Language: en-us
-    //	fOO: Bar
-    //	foo: two
-    //
-    // then
-    //
-    //	Header = map[string][]string{
-    //		"Accept-Encoding": {"gzip, deflate"},
-    //		"Accept-Language": {"en-us"},
-    //		"Foo": {"Bar", "two"},
-    //	}
-    //
-    // For incoming requests, the Host header is promoted to the
-    // Request.Host field and removed from the Header map.
-    //
-    // HTTP defines that header names are case-insensitive. The
-    // request parser implements this by using CanonicalHeaderKey,
-    // making the first character and any characters following a
-    // hyphen uppercase and the rest lowercase.
-    //
-    // For client requests, certain headers such as Content-Length
-    // and Connection are automatically written when needed and
-    // values in Header may be ignored. See the documentation
-    // for the Request.Write method.
-    // Header: Header,
-
-    // Body is the request's body.
-    //
-    // For client requests, a nil body means the request has no
-    // body, such as a GET request. The HTTP Client's Transport
-    // is responsible for calling the Close method.
-    //
-    // For server requests, the Request Body is always non-nil
-    // but will return EOF immediately when no body is present.
-    // The Server will close the request body. The ServeHTTP
-    // Handler does not need to.
-    // Body io.ReadCloser
-
-    // GetBody defines an optional func to return a new copy of
-    // Body. It is used for client requests when a redirect requires
-    // reading the body more than once. Use of GetBody still
-    // requires setting Body.
-    //
-    // For server requests, it is unused.
-    // GetBody func() (io.ReadCloser, error)
-
-    // ContentLength records the length of the associated content.
-    // The value -1 indicates that the length is unknown.
-    // Values >= 0 indicate that the given number of bytes may
-    // be read from Body.
-    //
-    // For client requests, a value of 0 with a non-nil Body is
-    // also treated as unknown.
-    pub content_length: i64,
-
-    // TransferEncoding lists the transfer encodings from outermost to
-    // innermost. An empty list denotes the "identity" encoding.
-    // TransferEncoding can usually be ignored; chunked encoding is
-    // automatically added and removed as necessary when sending and
-    // receiving requests.
-    // TransferEncoding []string
-
-    // Close indicates whether to close the connection after
-    // replying to this request (for servers) or after sending this
-    // request and reading its response (for clients).
-    //
-    // For server requests, the HTTP server handles this automatically
-    // and this field is not needed by Handlers.
-    //
-    // For client requests, setting this field prevents re-use of
-    // TCP connections between requests to the same hosts, as if
-    // Transport.DisableKeepAlives were set.
-    pub close: bool,
-
-    // For server requests, Host specifies the host on which the URL
-    // is sought. Per RFC 7230, section 5.4, this is either the value
-    // of the "Host" header or the host name given in the URL itself.
-    // It may be of the form "host:port". For international domain
-    // names, Host may be in Punycode or Unicode form. Use
-    // golang.org/x/net/idna to convert it to either format if
-    // needed.
-    // To prevent DNS rebinding attacks, server Handlers should
-    // validate that the Host header has a value for which the
-    // Handler considers itself authoritative. The included
-    // ServeMux supports patterns registered to particular host
-    // names and thus protects its registered Handlers.
-    //
-    // For client requests, Host optionally overrides the Host
-    // header to send. If empty, the Request.Write method uses
-    // the value of URL.Host. Host may contain an international
-    // domain name.
-    pub host: String,
-
-    // Form contains the parsed form data, including both the URL
-    // field's query parameters and the POST or PUT form data.
-    // This field is only available after ParseForm is called.
-    // The HTTP client ignores Form and uses Body instead.
-    // Form url.Values
-
-    // PostForm contains the parsed form data from POST, PATCH,
-    // or PUT body parameters.
-    //
-    // This field is only available after ParseForm is called.
-    // The HTTP client ignores PostForm and uses Body instead.
-    // PostForm url.Values
-
-    // MultipartForm is the parsed multipart form, including file uploads.
-    // This field is only available after ParseMultipartForm is called.
-    // The HTTP client ignores MultipartForm and uses Body instead.
-    // MultipartForm *multipart.Form
-
-    // Trailer specifies additional headers that are sent after the request
-    // body.
-    //
-    // For server requests, the Trailer map initially contains only the
-    // trailer keys, with nil values. (The client declares which trailers it
-    // will later send.)  While the handler is reading from Body, it must
-    // not reference Trailer. After reading from Body returns EOF, Trailer
-    // can be read again and will contain non-nil values, if they were sent
-    // by the client.
-    //
-    // For client requests, Trailer must be initialized to a map containing
-    // the trailer keys to later send. The values may be nil or their final
-    // values. The ContentLength must be 0 or -1, to send a chunked request.
-    // After the HTTP request is sent the map values can be updated while
-    // the request body is read. Once the body returns EOF, the caller must
-    // not mutate Trailer.
-    //
-    // Few HTTP clients, servers, or proxies support HTTP trailers.
-    // Trailer Header
-
-    // RemoteAddr allows HTTP servers and other software to record
-    // the network address that sent the request, usually for
-    // logging. This field is not filled in by ReadRequest and
-    // has no defined format. The HTTP server in this package
-    // sets RemoteAddr to an "IP:port" address before invoking a
-    // handler.
-    // This field is ignored by the HTTP client.
-    pub remote_addr: String,
-
-    // RequestURI is the unmodified request-target of the
-    // Request-Line (RFC 7230, Section 3.1.1) as sent by the client
-    // to a server. Usually the URL field should be used instead.
-    // It is an error to set this field in an HTTP client request.
-    pub request_uri: String, // TLS allows HTTP servers and other software to record
-                             // information about the TLS connection on which the request
-                             // was received. This field is not filled in by ReadRequest.
-                             // The HTTP server in this package sets the field for
-                             // TLS-enabled connections before invoking a handler;
-                             // otherwise it leaves the field nil.
-                             // This field is ignored by the HTTP client.
-                             // TLS *tls.ConnectionState
-
-                             // Cancel is an optional channel whose closure indicates that the client
-                             // request should be regarded as canceled. Not all implementations of
-                             // RoundTripper may support Cancel.
-                             //
-                             // For server requests, this field is not applicable.
-                             //
-                             // Deprecated: Use the Context and WithContext methods
-                             // instead. If a Request's Cancel field and context are both
-                             // set, it is undefined whether Cancel is respected.
-                             // Cancel <-chan struct{}
-
-                             // Response is the redirect response which caused this request
-                             // to be created. This field is only populated during client
-                             // redirects.
-                             // Response *Response
-
-                             // ctx is either the client or server context. It should only
-                             // be modified via copying the whole Request using WithContext.
-                             // It is unexported to prevent people from using Context wrong
-                             // and mutating the contexts held by callers of the same request.
-                             // ctx context.Context
+    method: Method,
+
+    // Target specifies the URL to access. Its path and query form the
+    // request-target, and its host is sent in the Host header unless
+    // the host field overrides it.
+    target: Addr,
+
+    // Headers contains the request header fields to be sent. Host and
+    // Content-Length are written automatically when they are not set.
+    headers: Headers,
+
+    // Body is the request's body. It is sent with Content-Length, or with
+    // the chunked transfer coding if its length is unknown. POST, PUT and
+    // PATCH requests always announce a body, even an empty one.
+    body: Body,
+
+    // Close indicates whether to close the connection after sending this
+    // request and reading its response.
+    close: bool,
+
+    // Host optionally overrides the Host header to send. If empty, the
+    // host of the target is used.
+    host: Option<String>,
+
+    // AbsoluteForm sends the whole target URL as the request-target, as
+    // required for requests to a forward proxy (RFC 7230, Section 5.3.2).
+    absolute_form: bool,
+}
+
+impl Request {
+    pub fn new(method: Method, target: Addr) -> Self {
+        Request {
+            method,
+            target,
+            headers: Headers::new(),
+            body: Body::default(),
+            close: false,
+            host: None,
+            absolute_form: false,
+        }
+    }
+
+    pub fn builder() -> RequestBuilder {
+        RequestBuilder::new()
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn target(&self) -> &Addr {
+        &self.target
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    pub fn set_absolute_form(&mut self, absolute_form: bool) {
+        self.absolute_form = absolute_form;
+    }
+
+    pub fn set_body<B: Into<Body>>(&mut self, body: B) {
+        self.body = body.into();
+    }
+
+    pub fn set_body_reader<R: Read + Send + 'static>(&mut self, reader: R, len: Option<u64>) {
+        self.body = Body::Reader(Box::new(reader), len);
+    }
+
+    // Writes the request head and streams the body to the writer. A body
+    // reader is consumed by this call.
+    pub fn write_to<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        // A Custom method is not checked when it is constructed.
+        if !is_token(&self.method.to_string()) {
+            return Err(Error::InvalidMethod);
+        }
+        // CONNECT uses the authority-form of the request-target
+        // (RFC 7231, Section 4.3.6).
+        let request_target = match self.method {
+            Method::Connect => self.target.authority()?,
+            _ if self.absolute_form => self.target.absolute_uri()?,
+            _ => self.target.request_uri(),
+        };
+        let mut head = format!("{} {} HTTP/1.1\r\n", self.method, request_target);
+        if !self.headers.contains("Host") {
+            let host = match (&self.host, &self.method) {
+                (Some(host), _) => host.clone(),
+                (None, Method::Connect) => self.target.authority()?,
+                (None, _) => self.target.host_header()?,
+            };
+            head.push_str(&format!("Host: {}\r\n", host));
+        }
+        if self.close && !self.headers.contains("Connection") {
+            head.push_str("Connection: close\r\n");
+        }
+        head.push_str(&self.headers.to_string());
+        // The body length, or None for the chunked transfer coding. A
+        // Transfer-Encoding set by the caller must end with chunked, and a
+        // Content-Length set by the caller must match the body, as the body
+        // is written accordingly (RFC 7230, Section 3.3).
+        let len = if self.headers.contains("Transfer-Encoding") {
+            if self.headers.contains("Content-Length") || !is_chunked(&self.headers) {
+                return Err(Error::InvalidTransferEncoding);
+            }
+            None
+        } else if let Some(len) = content_length(&self.headers)? {
+            if self.body.len().is_some_and(|body_len| body_len != len) {
+                return Err(Error::InvalidContentLength);
+            }
+            Some(len)
+        } else {
+            match self.body.len() {
+                Some(len) => {
+                    if self.has_body() {
+                        head.push_str(&format!("Content-Length: {}\r\n", len));
+                    }
+                    Some(len)
+                }
+                None => {
+                    head.push_str("Transfer-Encoding: chunked\r\n");
+                    None
+                }
+            }
+        };
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())?;
+        match (len, &mut self.body) {
+            (None, body) => {
+                let mut chunked = ChunkedWriter::new(&mut *writer);
+                match body {
+                    Body::Bytes(bytes) => chunked.write_all(bytes)?,
+                    Body::Reader(reader, _) => {
+                        io::copy(reader, &mut chunked)?;
+                    }
+                }
+                chunked.finish()?;
+            }
+            (Some(_), Body::Bytes(bytes)) => writer.write_all(bytes)?,
+            (Some(len), Body::Reader(reader, _)) => {
+                if io::copy(&mut reader.take(len), writer)? != len {
+                    return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn has_body(&self) -> bool {
+        match (&self.method, &self.body) {
+            (Method::Post, _) | (Method::Put, _) | (Method::Patch, _) => true,
+            (_, body) => !body.is_empty(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RequestBuilder {
+    method: Method,
+    target: Option<String>,
+    headers: Headers,
+    body: Body,
+    close: bool,
+    host: Option<String>,
+    // The first invalid header, reported by build.
+    error: Option<Error>,
+}
+
+impl RequestBuilder {
+    pub fn new() -> Self {
+        RequestBuilder {
+            method: Method::Get,
+            target: None,
+            headers: Headers::new(),
+            body: Body::default(),
+            close: false,
+            host: None,
+            error: None,
+        }
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        if let Err(err) = self.headers.append(name, value) {
+            self.error.get_or_insert(err);
+        }
+        self
+    }
+
+    pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn body_reader<R: Read + Send + 'static>(mut self, reader: R, len: Option<u64>) -> Self {
+        self.body = Body::Reader(Box::new(reader), len);
+        self
+    }
+
+    pub fn close(mut self, close: bool) -> Self {
+        self.close = close;
+        self
+    }
+
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<Request> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if !is_token(&self.method.to_string()) {
+            return Err(Error::InvalidMethod);
+        }
+        if let Some(host) = &self.host {
+            if host.is_empty() || !is_field_value(host) {
+                return Err(Error::InvalidHeader);
+            }
+        }
+        let target = self.target.ok_or(Error::InvalidHost)?;
+        Ok(Request {
+            method: self.method,
+            target: target.parse()?,
+            headers: self.headers,
+            body: self.body,
+            close: self.close,
+            host: self.host,
+            absolute_form: false,
+        })
+    }
+}
+
+impl Default for RequestBuilder {
+    fn default() -> Self {
+        RequestBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_string(request: &mut Request) -> String {
+        let mut buf = Vec::new();
+        request.write_to(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn method() {
+        assert_eq!("GET".parse::<Method>().unwrap(), Method::Get);
+        assert_eq!(
+            "PROPFIND".parse::<Method>().unwrap(),
+            Method::Custom("PROPFIND".to_string())
+        );
+        assert!("GET /".parse::<Method>().is_err());
+        assert!("".parse::<Method>().is_err());
+    }
+
+    #[test]
+    fn request_to_vec() {
+        let mut request = Request::builder()
+            .method(Method::Put)
+            .target("http://example.org:8080/items?id=1")
+            .header("Content-Type", "text/plain")
+            .body("hello")
+            .build()
+            .unwrap();
+        assert_eq!(
+            to_string(&mut request),
+            "PUT /items?id=1 HTTP/1.1\r\nHost: example.org:8080\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhello"
+        );
+    }
+
+    #[test]
+    fn request_host_override() {
+        let mut request = Request::builder()
+            .target("https://127.0.0.1/")
+            .host("example.org")
+            .close(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            to_string(&mut request),
+            "GET / HTTP/1.1\r\nHost: example.org\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn request_body_reader() {
+        let mut request = Request::builder()
+            .method(Method::Post)
+            .target("http://example.org/upload")
+            .body_reader(&b"streamed body"[..], Some(8))
+            .build()
+            .unwrap();
+        assert_eq!(
+            to_string(&mut request),
+            "POST /upload HTTP/1.1\r\nHost: example.org\r\nContent-Length: 8\r\n\r\nstreamed"
+        );
+        let mut request = Request::builder()
+            .method(Method::Post)
+            .target("http://example.org/upload")
+            .body_reader(&b"streamed body"[..], None)
+            .build()
+            .unwrap();
+        assert_eq!(
+            to_string(&mut request),
+            "POST /upload HTTP/1.1\r\nHost: example.org\r\nTransfer-Encoding: chunked\r\n\r\nD\r\nstreamed body\r\n0\r\n\r\n"
+        );
+        let mut request = Request::builder()
+            .method(Method::Post)
+            .target("http://example.org/upload")
+            .body_reader(&b"short"[..], Some(8))
+            .build()
+            .unwrap();
+        assert!(request.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn request_framing() {
+        let mut request = Request::builder()
+            .method(Method::Post)
+            .target("http://example.org/upload")
+            .header("Transfer-Encoding", "chunked")
+            .body("hello")
+            .build()
+            .unwrap();
+        assert_eq!(
+            to_string(&mut request),
+            "POST /upload HTTP/1.1\r\nHost: example.org\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n"
+        );
+        let mut request = Request::builder()
+            .method(Method::Post)
+            .target("http://example.org/upload")
+            .header("Content-Length", "8")
+            .body_reader(&b"streamed body"[..], None)
+            .build()
+            .unwrap();
+        assert_eq!(
+            to_string(&mut request),
+            "POST /upload HTTP/1.1\r\nHost: example.org\r\nContent-Length: 8\r\n\r\nstreamed"
+        );
+        for (name, value, body) in &[
+            ("Content-Length", "4", "hello"),
+            ("Content-Length", "x", "hello"),
+            ("Transfer-Encoding", "gzip", "hello"),
+        ] {
+            let mut request = Request::builder()
+                .method(Method::Post)
+                .target("http://example.org/upload")
+                .header(name, value)
+                .body(*body)
+                .build()
+                .unwrap();
+            let mut buf = Vec::new();
+            assert!(request.write_to(&mut buf).is_err());
+            assert!(buf.is_empty());
+        }
+        let mut request = Request::builder()
+            .method(Method::Post)
+            .target("http://example.org/upload")
+            .header("Content-Length", "5")
+            .header("Transfer-Encoding", "chunked")
+            .body("hello")
+            .build()
+            .unwrap();
+        assert!(request.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn request_injection() {
+        let builder = || Request::builder().target("http://example.org/");
+        assert!(builder().header("X", "a\r\nEvil: 1").build().is_err());
+        assert!(builder().header("X\r\nEvil", "1").build().is_err());
+        assert!(builder().host("example.org\r\nEvil: 1").build().is_err());
+        let custom = || Method::Custom("GET / HTTP/1.1\r\n\r\nGET".to_string());
+        assert!(builder().method(custom()).build().is_err());
+        let mut request = Request::new(custom(), "http://example.org/".parse().unwrap());
+        let mut buf = Vec::new();
+        assert!(request.write_to(&mut buf).is_err());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn request_connect() {
+        let mut request = Request::new(Method::Connect, "https://[::1]/path".parse().unwrap());
+        assert_eq!(
+            to_string(&mut request),
+            "CONNECT [::1]:443 HTTP/1.1\r\nHost: [::1]:443\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn request_absolute_form() {
+        let mut request = Request::new(
+            Method::Get,
+            "http://example.org:8080/a?b=c#d".parse().unwrap(),
+        );
+        request.set_absolute_form(true);
+        assert_eq!(
+            to_string(&mut request),
+            "GET http://example.org:8080/a?b=c HTTP/1.1\r\nHost: example.org:8080\r\n\r\n"
+        );
+    }
 }
@@ -0,0 +1,534 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use crate::chunked::ChunkedReader;
+use crate::error::{Error, Result};
+use crate::headers::Headers;
+use crate::request::Method;
+use crate::stream::Stream;
+
+// The longest response head accepted, and the longest chunk size line and
+// trailer section of a chunked body.
+pub(crate) const MAX_HEAD_LEN: usize = 16 * 1024;
+
+// A response whose body is read from the connection while it is consumed.
+pub type StreamResponse<'a> = Response<StreamBody<'a>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response<B = Vec<u8>> {
+    version: String,
+    status: u16,
+    reason: String,
+    headers: Headers,
+    body: B,
+    trailers: Headers,
+}
+
+impl<B> Response<B> {
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn status_code(&self) -> u16 {
+        self.status
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    fn map_body<C, F: FnOnce(B) -> C>(self, f: F) -> Response<C> {
+        Response {
+            version: self.version,
+            status: self.status,
+            reason: self.reason,
+            headers: self.headers,
+            body: f(self.body),
+            trailers: self.trailers,
+        }
+    }
+
+    // Reads the rest of the body into memory, keeping the trailers that
+    // came after it.
+    fn buffered(mut self, trailers: fn(&B) -> Option<&Headers>) -> Result<Response>
+    where
+        B: Read,
+    {
+        let mut body = Vec::new();
+        self.body.read_to_end(&mut body)?;
+        let trailers = trailers(&self.body).cloned().unwrap_or_default();
+        let mut response = self.map_body(|_| body);
+        response.trailers = trailers;
+        Ok(response)
+    }
+}
+
+impl Response {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Response> {
+        Response::read_from(&mut &bytes[..], &Method::Get)
+    }
+
+    // Reads exactly one response from the reader: the head, and then a body
+    // delimited by Transfer-Encoding, Content-Length or the connection close,
+    // so the rest of the reader can be used for the next response.
+    pub fn read_from<R: BufRead>(reader: &mut R, method: &Method) -> Result<Response> {
+        Response::read_streamed(reader, method)?.into_buffered()
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn trailers(&self) -> &Headers {
+        &self.trailers
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+}
+
+impl<R: BufRead> Response<BodyReader<R>> {
+    // Reads the response head and leaves the body in the reader. The body
+    // must be read to the end before the next response can be read.
+    pub fn read_streamed(mut reader: R, method: &Method) -> Result<Self> {
+        let (version, status, reason, headers) = loop {
+            let (version, status, reason, headers) = read_head(&mut reader)?;
+            // Interim responses are followed by the final one, except for
+            // 101 (Switching Protocols) which ends the HTTP exchange.
+            if !(100..=199).contains(&status) || status == 101 {
+                break (version, status, reason, headers);
+            }
+        };
+        let body = if !has_body(method, status) {
+            BodyReader::Empty
+        } else if is_chunked(&headers) {
+            BodyReader::Chunked(ChunkedReader::new(reader))
+        } else if headers.contains("Transfer-Encoding") {
+            // Transfer-Encoding overrides Content-Length, and without chunked
+            // as the final coding the body runs until the connection closes
+            // (RFC 7230, Section 3.3.3).
+            BodyReader::Close(reader)
+        } else if let Some(len) = content_length(&headers)? {
+            BodyReader::Length(reader.take(len))
+        } else {
+            BodyReader::Close(reader)
+        };
+        Ok(Response {
+            version,
+            status,
+            reason,
+            headers,
+            body,
+            trailers: Headers::new(),
+        })
+    }
+
+    pub fn body(&mut self) -> &mut BodyReader<R> {
+        &mut self.body
+    }
+
+    pub fn into_body(self) -> BodyReader<R> {
+        self.body
+    }
+
+    // Reads the rest of the body into memory.
+    pub fn into_buffered(self) -> Result<Response> {
+        self.buffered(BodyReader::trailers)
+    }
+}
+
+impl<'a> StreamResponse<'a> {
+    // Reads the response head from a persistent connection, which stays
+    // marked as broken if that fails. It is marked again if the response is
+    // dropped before the end of its body, as the rest of the body would be
+    // taken for the next response.
+    pub(crate) fn read_from_conn(
+        reader: &'a mut BufReader<Stream>,
+        broken: &'a mut bool,
+        method: &Method,
+    ) -> Result<Self> {
+        let response = Response::read_streamed(reader, method)?;
+        *broken = false;
+        Ok(response.map_body(move |reader| StreamBody { reader, broken }))
+    }
+
+    pub fn body(&mut self) -> &mut StreamBody<'a> {
+        &mut self.body
+    }
+
+    pub fn into_body(self) -> StreamBody<'a> {
+        self.body
+    }
+
+    // Reads the rest of the body into memory.
+    pub fn into_buffered(self) -> Result<Response> {
+        self.buffered(StreamBody::trailers)
+    }
+}
+
+// BodyReader reads a response body as it arrives, stopping at its end as
+// given by the message framing.
+#[derive(Debug)]
+pub enum BodyReader<R> {
+    Empty,
+    Length(io::Take<R>),
+    Chunked(ChunkedReader<R>),
+    Close(R),
+}
+
+impl<R: BufRead> BodyReader<R> {
+    // Trailer fields of a chunked body, available once it has been read
+    // to the end.
+    pub fn trailers(&self) -> Option<&Headers> {
+        match self {
+            BodyReader::Chunked(chunked) if chunked.is_done() => Some(chunked.trailers()),
+            _ => None,
+        }
+    }
+
+    // Whether the body has been read to the end and the reader is at the
+    // start of the next message. Never for a body delimited by the
+    // connection close.
+    pub fn is_done(&self) -> bool {
+        match self {
+            BodyReader::Empty => true,
+            BodyReader::Length(reader) => reader.limit() == 0,
+            BodyReader::Chunked(reader) => reader.is_done(),
+            BodyReader::Close(_) => false,
+        }
+    }
+}
+
+impl<R: BufRead> Read for BodyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = {
+            let body = self.fill_buf()?;
+            let len = body.len().min(buf.len());
+            buf[..len].copy_from_slice(&body[..len]);
+            len
+        };
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for BodyReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            BodyReader::Empty => Ok(&[]),
+            BodyReader::Length(reader) => {
+                let limit = reader.limit();
+                let buf = reader.fill_buf()?;
+                if buf.is_empty() && limit > 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(buf)
+            }
+            BodyReader::Chunked(reader) => reader.fill_buf(),
+            BodyReader::Close(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            BodyReader::Empty => (),
+            BodyReader::Length(reader) => reader.consume(amt),
+            BodyReader::Chunked(reader) => reader.consume(amt),
+            BodyReader::Close(reader) => reader.consume(amt),
+        }
+    }
+}
+
+// StreamBody is the body of a StreamResponse, read from the connection.
+#[derive(Debug)]
+pub struct StreamBody<'a> {
+    reader: BodyReader<&'a mut BufReader<Stream>>,
+    broken: &'a mut bool,
+}
+
+impl StreamBody<'_> {
+    pub fn trailers(&self) -> Option<&Headers> {
+        self.reader.trailers()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.reader.is_done()
+    }
+}
+
+impl Read for StreamBody<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl BufRead for StreamBody<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl Drop for StreamBody<'_> {
+    fn drop(&mut self) {
+        if !self.reader.is_done() {
+            *self.broken = true;
+        }
+    }
+}
+
+// Responses to HEAD requests, all 1xx (Informational), 204 (No Content) and
+// 304 (Not Modified) responses, and 2xx responses to CONNECT never include a
+// message body (RFC 7230, Section 3.3.3).
+fn has_body(method: &Method, status: u16) -> bool {
+    !matches!(
+        (method, status),
+        (Method::Head, _) | (Method::Connect, 200..=299) | (_, 100..=199) | (_, 204) | (_, 304)
+    )
+}
+
+fn parse_status_line(line: &str) -> Result<(String, u16, String)> {
+    // Status-Line = HTTP-Version SP Status-Code SP Reason-Phrase CRLF
+    let mut parts = line.splitn(3, ' ');
+    let version = parts.next().ok_or(Error::InvalidStatusLine)?;
+    if !version.starts_with("HTTP/") {
+        return Err(Error::InvalidStatusLine);
+    }
+    let code = parts.next().ok_or(Error::InvalidStatusLine)?;
+    if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::InvalidStatusLine);
+    }
+    let status = code.parse().map_err(|_| Error::InvalidStatusLine)?;
+    // The reason phrase is optional and may be empty.
+    let reason = parts.next().unwrap_or("").trim();
+    Ok((version.to_string(), status, reason.to_string()))
+}
+
+pub(crate) fn parse_header_line(line: &str, headers: &mut Headers) -> Result<()> {
+    // header-field = field-name ":" OWS field-value OWS
+    let pos = line.find(':').ok_or(Error::InvalidHeader)?;
+    let name = &line[..pos];
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(Error::InvalidHeader);
+    }
+    headers.append(name, line[pos + 1..].trim())
+}
+
+// Reads a line of at most limit bytes and subtracts its length from the
+// limit, so that the lines of a head share one limit and a peer cannot
+// exhaust the memory with an endless line. Bytes that are not UTF-8, such
+// as obs-text in field values (RFC 9110, Section 5.5), are taken as
+// ISO-8859-1.
+pub(crate) fn read_line<R: BufRead>(reader: &mut R, limit: &mut usize) -> Result<String> {
+    if *limit == 0 {
+        return Err(Error::WrongHttp);
+    }
+    let mut line = Vec::new();
+    let len = reader
+        .by_ref()
+        .take(*limit as u64)
+        .read_until(b'\n', &mut line)?;
+    if len == 0 {
+        return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+    }
+    *limit -= len;
+    if line.ends_with(b"\n") {
+        line.pop();
+    } else if *limit == 0 {
+        return Err(Error::WrongHttp);
+    }
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(String::from_utf8(line)
+        .unwrap_or_else(|err| err.into_bytes().iter().map(|&byte| byte as char).collect()))
+}
+
+fn read_head<R: BufRead>(reader: &mut R) -> Result<(String, u16, String, Headers)> {
+    let mut limit = MAX_HEAD_LEN;
+    let (version, status, reason) = parse_status_line(&read_line(reader, &mut limit)?)?;
+    let mut headers = Headers::new();
+    loop {
+        let line = read_line(reader, &mut limit)?;
+        if line.is_empty() {
+            break;
+        }
+        parse_header_line(&line, &mut headers)?;
+    }
+    Ok((version, status, reason, headers))
+}
+
+pub(crate) fn is_chunked(headers: &Headers) -> bool {
+    // chunked must be the final transfer coding (RFC 7230, Section 3.3.3)
+    headers
+        .get_all("Transfer-Encoding")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .last()
+        .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+}
+
+pub(crate) fn content_length(headers: &Headers) -> Result<Option<u64>> {
+    let mut length = None;
+    for value in headers.get_all("Content-Length") {
+        for value in value.split(',') {
+            // Only digits, the integer parser also takes a leading sign.
+            let value = value.trim();
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(Error::InvalidContentLength);
+            }
+            let value = value.parse().map_err(|_| Error::InvalidContentLength)?;
+            if length.is_some_and(|length| length != value) {
+                return Err(Error::InvalidContentLength);
+            }
+            length = Some(value);
+        }
+    }
+    Ok(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_response() {
+        let response = Response::from_bytes(
+            b"HTTP/1.1 502 Bad Gateway\r\nContent-Type: text/plain\r\nX-Proxy:  squid \r\n\r\nerror",
+        )
+        .unwrap();
+        assert_eq!(response.version(), "HTTP/1.1");
+        assert_eq!(response.status_code(), 502);
+        assert_eq!(response.reason(), "Bad Gateway");
+        assert!(!response.is_success());
+        assert_eq!(response.header("content-type"), Some("text/plain"));
+        assert_eq!(response.header("x-proxy"), Some("squid"));
+        assert_eq!(response.body(), b"error");
+    }
+
+    #[test]
+    fn parse_empty_reason() {
+        let response = Response::from_bytes(b"HTTP/1.0 200\r\n\r\n").unwrap();
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.reason(), "");
+        assert!(response.headers().is_empty());
+        assert!(response.body().is_empty());
+    }
+
+    #[test]
+    fn parse_without_body() {
+        let bytes = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+        let response = Response::read_from(&mut &bytes[..], &Method::Head).unwrap();
+        assert_eq!(response.header("content-length"), Some("5"));
+        assert!(response.body().is_empty());
+        let response = Response::from_bytes(b"HTTP/1.1 204 No Content\r\n\r\nx").unwrap();
+        assert!(response.body().is_empty());
+        let response = Response::from_bytes(b"HTTP/1.1 304 Not Modified\r\n\r\nx").unwrap();
+        assert!(response.body().is_empty());
+    }
+
+    #[test]
+    fn read_keep_alive() {
+        let bytes = b"HTTP/1.1 100 Continue\r\n\r\n\
+HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello\
+HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nChecksum: 42\r\n\r\n\
+HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+        let mut reader = &bytes[..];
+        let response = Response::read_from(&mut reader, &Method::Get).unwrap();
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), b"hello");
+        let response = Response::read_from(&mut reader, &Method::Get).unwrap();
+        assert_eq!(response.body(), b"hello world");
+        assert_eq!(response.trailers().get("checksum"), Some("42"));
+        let response = Response::read_from(&mut reader, &Method::Get).unwrap();
+        assert_eq!(response.status_code(), 404);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn read_streamed() {
+        let bytes = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+5\r\nhello\r\n6\r\n world\r\n0\r\nChecksum: 42\r\n\r\n\
+HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nnext";
+        let mut reader = &bytes[..];
+        let mut response = Response::read_streamed(&mut reader, &Method::Get).unwrap();
+        let mut line = String::new();
+        response.body().read_line(&mut line).unwrap();
+        assert_eq!(line, "hello world");
+        assert_eq!(
+            response.body().trailers().unwrap().get("checksum"),
+            Some("42")
+        );
+        let mut response = Response::read_streamed(&mut reader, &Method::Get).unwrap();
+        let mut body = String::new();
+        response.body().read_to_string(&mut body).unwrap();
+        assert_eq!(body, "next");
+        let mut gzip =
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\nContent-Length: 2\r\n\r\nabcd"[..];
+        let mut response = Response::read_streamed(&mut gzip, &Method::Get).unwrap();
+        let mut body = Vec::new();
+        response.body().read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"abcd");
+        let mut short = &b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nshort"[..];
+        let mut response = Response::read_streamed(&mut short, &Method::Get).unwrap();
+        assert!(response.body().read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn parse_obs_text() {
+        let response =
+            Response::from_bytes(b"HTTP/1.1 200 OK\r\nX-Name: Jos\xe9\r\nX-Utf8: \xc3\xa9\r\n\r\n")
+                .unwrap();
+        assert_eq!(response.header("x-name"), Some("Jos\u{e9}"));
+        assert_eq!(response.header("x-utf8"), Some("\u{e9}"));
+    }
+
+    #[test]
+    fn parse_head_limit() {
+        let mut long = b"HTTP/1.1 200 OK\r\nX: ".to_vec();
+        long.resize(MAX_HEAD_LEN, b'a');
+        long.extend_from_slice(b"\r\n\r\n");
+        assert!(Response::from_bytes(&long).is_err());
+        let mut endless = b"HTTP/1.1 200 OK\r\n".to_vec();
+        endless.extend(b"X: a\r\n".iter().cycle().take(MAX_HEAD_LEN));
+        assert!(Response::from_bytes(&endless).is_err());
+        let mut line = String::from("HTTP/1.1 200 OK\r\nX: ");
+        line.push_str(&"a".repeat(MAX_HEAD_LEN - line.len() - 4));
+        line.push_str("\r\n\r\n");
+        assert!(Response::from_bytes(line.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Response::from_bytes(b"HTTP/1.1 200 OK\r\n").is_err());
+        assert!(Response::from_bytes(b"SSH-2.0 200 OK\r\n\r\n").is_err());
+        assert!(Response::from_bytes(b"HTTP/1.1 2000 OK\r\n\r\n").is_err());
+        assert!(Response::from_bytes(b"HTTP/1.1 +20 OK\r\n\r\n").is_err());
+        assert!(
+            Response::from_bytes(b"HTTP/1.1 200 OK\r\nContent-Length: +5\r\n\r\nhello").is_err()
+        );
+        assert!(Response::from_bytes(b"HTTP/1.1 200 OK\r\nBad Header\r\n\r\n").is_err());
+        assert!(
+            Response::from_bytes(b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nshort").is_err()
+        );
+        assert!(Response::from_bytes(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"
+        )
+        .is_err());
+    }
+}
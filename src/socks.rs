@@ -1,12 +1,23 @@
 // #![allow(dead_code)]
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
 use std::io::{self, Read, Write};
-use std::net::{Ipv4Addr, Ipv6Addr, TcpStream};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use sha2::{Digest, Sha256};
 use url::Host;
 
 use crate::addr::Addr;
+use crate::conn::Conn;
 use crate::error::{Error, Result};
+use crate::request::Request;
+use crate::response::{Response, StreamResponse};
 use crate::stream::Stream;
+use crate::tls::{TlsConfig, TlsInfo};
 
 #[derive(Clone, Copy)]
 enum AuthMethod {
@@ -14,36 +25,134 @@ enum AuthMethod {
     Plain = 2,
 }
 
-struct SocksAuth {
-    method: AuthMethod,
+#[derive(Clone, Copy)]
+enum Command {
+    Connect = 1,
+    Bind = 2,
+    UdpAssociate = 3,
+    Resolve = 0xF0,
+    ResolvePtr = 0xF1,
+}
+
+// Authenticator implements a SOCKS5 authentication method. Methods other
+// than the built-in no authentication (0x00) and username/password (0x02)
+// can be implemented outside the crate, private methods use the numbers
+// 0x80–0xFE.
+pub trait Authenticator {
+    // The method number offered in the greeting.
+    fn method(&self) -> u8;
+
+    // Runs the method-specific subnegotiation after the server chose this
+    // method.
+    fn authenticate(&self, socket: &mut TcpStream) -> Result<()>;
+}
+
+struct NoAuth;
+
+impl Authenticator for NoAuth {
+    fn method(&self) -> u8 {
+        AuthMethod::NoAuth as u8
+    }
+
+    fn authenticate(&self, _socket: &mut TcpStream) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct PlainAuth {
     username: Vec<u8>,
     password: Vec<u8>,
 }
 
+impl Authenticator for PlainAuth {
+    fn method(&self) -> u8 {
+        AuthMethod::Plain as u8
+    }
+
+    fn authenticate(&self, socket: &mut TcpStream) -> Result<()> {
+        // For username/password authentication the client's authentication request is
+        //     field 1: version number, 1 byte (0x01 for current version of username/password authentication)
+        let mut packet = vec![1u8];
+        //     field 2: username length, 1 byte
+        packet.push(self.username.len() as u8);
+        //     field 3: username, 1–255 bytes
+        packet.append(&mut self.username.clone());
+        //     field 4: password length, 1 byte
+        packet.push(self.password.len() as u8);
+        //     field 5: password, 1–255 bytes
+        packet.append(&mut self.password.clone());
+        socket.write_all(&packet)?;
+        let mut buf = [0u8; 2];
+        socket.read_exact(&mut buf)?;
+        // Server response for username/password authentication:
+        //     field 1: version, 1 byte (0x01 for current version of username/password authentication)
+        //     field 2: status code, 1 byte
+        //         0x00: success
+        //         any other value is a failure, connection must be closed
+        match (buf[0] != 1u8, buf[1] != 0u8) {
+            (true, _) => Err(Error::InvalidAuthVersion),
+            (_, true) => Err(Error::AuthFailure),
+            _ => Ok(()),
+        }
+    }
+}
+
+// SocksAuth is the list of authentication methods offered to the server,
+// in order of preference. The server picks one of them.
+pub struct SocksAuth {
+    methods: Vec<Box<dyn Authenticator>>,
+}
+
 impl SocksAuth {
+    // Username/password authentication, falling back to no authentication
+    // if the server does not require credentials.
     pub fn new_plain(username: &str, password: &str) -> Self {
-        SocksAuth {
-            method: AuthMethod::Plain,
-            username: username.as_bytes().to_vec(),
-            password: password.as_bytes().to_vec(),
-        }
+        SocksAuth::new_empty()
+            .with_method(PlainAuth {
+                username: username.as_bytes().to_vec(),
+                password: password.as_bytes().to_vec(),
+            })
+            .with_method(NoAuth)
     }
 
     pub fn new() -> Self {
+        SocksAuth::new_empty().with_method(NoAuth)
+    }
+
+    // No methods at all, to be filled with with_method.
+    pub fn new_empty() -> Self {
         SocksAuth {
-            method: AuthMethod::NoAuth,
-            username: Vec::new(),
-            password: Vec::new(),
+            methods: Vec::new(),
         }
     }
+
+    // Adds a method, replacing an earlier one with the same number.
+    pub fn with_method<A: Authenticator + 'static>(mut self, authenticator: A) -> Self {
+        self.methods
+            .retain(|method| method.method() != authenticator.method());
+        self.methods.push(Box::new(authenticator));
+        self
+    }
 }
 
-fn initial_greeting(socket: &mut TcpStream, auth: &SocksAuth) -> io::Result<()> {
+impl Default for SocksAuth {
+    fn default() -> Self {
+        SocksAuth::new()
+    }
+}
+
+fn initial_greeting(socket: &mut TcpStream, auth: &SocksAuth) -> Result<()> {
     // The initial greeting from the client
     //      field 1: SOCKS version, 1 byte (0x05 for this version)
     //      field 2: number of authentication methods supported, 1 byte
     //      field 3: authentication methods, variable length, 1 byte per method supported
-    socket.write_all(&[5u8, 1u8, auth.method as u8])
+    if auth.methods.is_empty() || auth.methods.len() > 255 {
+        return Err(Error::InvalidAuthMethod);
+    }
+    let mut packet = vec![5u8, auth.methods.len() as u8];
+    packet.extend(auth.methods.iter().map(|method| method.method()));
+    socket.write_all(&packet)?;
+    Ok(())
 }
 
 fn choise_communicated(socket: &mut TcpStream) -> Result<[u8; 2]> {
@@ -63,39 +172,13 @@ fn is_valid_socks_version(value: u8) -> Result<()> {
 }
 
 fn try_auth(socket: &mut TcpStream, value: u8, auth: &SocksAuth) -> Result<()> {
-    if value == auth.method as u8 && value == 2u8 {
-        // For username/password authentication the client's authentication request is
-        //     field 1: version number, 1 byte (0x01 for current version of username/password authentication)
-        let mut packet = vec![1u8];
-        //     field 2: username length, 1 byte
-        packet.push(auth.username.len() as u8);
-        //     field 3: username, 1–255 bytes
-        packet.append(&mut auth.username.clone());
-        //     field 4: password length, 1 byte
-        packet.push(auth.password.len() as u8);
-        //     field 5: password, 1–255 bytes
-        packet.append(&mut auth.password.clone());
-        socket.write_all(&packet)?;
-        let mut buf = [0u8; 2];
-        socket.read_exact(&mut buf)?;
-        // Server response for username/password authentication:
-        //     field 1: version, 1 byte (0x01 for current version of username/password authentication)
-        //     field 2: status code, 1 byte
-        //         0x00: success
-        //         any other value is a failure, connection must be closed
-        match (buf[0] != 1u8, buf[1] != 0u8) {
-            (true, _) => Err(Error::InvalidAuthVersion),
-            (_, true) => Err(Error::AuthFailure),
-            _ => Ok(()),
-        }
-    } else if value == auth.method as u8 {
-        Ok(())
-    } else {
-        Err(Error::InvalidAuthMethod)
+    match auth.methods.iter().find(|method| method.method() == value) {
+        Some(method) => method.authenticate(socket),
+        None => Err(Error::InvalidAuthMethod),
     }
 }
 
-fn request_connection(socket: &mut TcpStream, target: Vec<u8>) -> io::Result<()> {
+fn request_connection(socket: &mut TcpStream, command: Command, target: Vec<u8>) -> io::Result<()> {
     let mut packet = Vec::new();
     // The client's connection request is
     //     field 1: SOCKS version number, 1 byte (0x05 for this version)
@@ -104,7 +187,7 @@ fn request_connection(socket: &mut TcpStream, target: Vec<u8>) -> io::Result<()>
     //         0x01: establish a TCP/IP stream connection
     //         0x02: establish a TCP/IP port binding
     //         0x03: associate a UDP port
-    packet.push(1u8);
+    packet.push(command as u8);
     //     field 3: reserved, must be 0x00, 1 byte
     packet.push(0u8);
     //     field 4: address type, 1 byte:
@@ -156,7 +239,84 @@ fn get_server_reponse(socket: &mut TcpStream) -> Result<()> {
     }
 }
 
-fn get_host(socket: &mut TcpStream) -> Result<Host> {
+fn request_connection_v4(
+    socket: &mut TcpStream,
+    target: &Addr,
+    userid: &[u8],
+    resolve: Resolve,
+) -> Result<()> {
+    // The SOCKS4 client's connection request is
+    //     field 1: SOCKS version number, 1 byte (0x04 for this version)
+    //     field 2: command code, 1 byte (0x01 to establish a TCP/IP stream connection)
+    let mut packet = vec![4u8, 1u8];
+    //     field 3: port number in a network byte order, 2 bytes
+    packet.append(&mut target.port());
+    //     field 4: IPv4 address in a network byte order, 4 bytes
+    //         SOCKS4a sets it to 0.0.0.x with nonzero x when the proxy is to
+    //         resolve the domain name
+    let domain = match target.addr_type()? {
+        1u8 => {
+            packet.append(&mut target.host_vec()?);
+            None
+        }
+        3u8 if resolve == Resolve::Local => {
+            let ipv4 = target
+                .socket_addrs()?
+                .into_iter()
+                .find_map(|addr| match addr.ip() {
+                    IpAddr::V4(ipv4) => Some(ipv4),
+                    IpAddr::V6(_) => None,
+                })
+                .ok_or(Error::InvalidAddressType)?;
+            packet.extend_from_slice(&ipv4.octets());
+            None
+        }
+        3u8 => {
+            packet.extend_from_slice(&[0u8, 0u8, 0u8, 1u8]);
+            Some(target.host_vec()?)
+        }
+        _ => return Err(Error::InvalidAddressType),
+    };
+    //     field 5: the user ID, variable length, null-terminated
+    packet.extend_from_slice(userid);
+    packet.push(0u8);
+    //     field 6: SOCKS4a only, the domain name, variable length, null-terminated
+    if let Some(mut domain) = domain {
+        packet.append(&mut domain);
+        packet.push(0u8);
+    }
+    socket.write_all(&packet)?;
+    Ok(())
+}
+
+fn get_server_reponse_v4(socket: &mut TcpStream) -> Result<SocksAddr> {
+    let mut buf = [0u8; 8];
+    socket.read_exact(&mut buf)?;
+    // SOCKS4 server response:
+    //     field 1: reply version, 1 byte (0x00)
+    if buf[0] != 0u8 {
+        return Err(Error::InvalidServerVersion);
+    }
+    //     field 2: status, 1 byte:
+    //         0x5A: request granted
+    //         0x5B: request rejected or failed
+    //         0x5C: request failed because client is not running identd (or not reachable from server)
+    //         0x5D: request failed because client's identd could not confirm the user ID in the request
+    //     field 3: server bound port number in a network byte order, 2 bytes
+    //     field 4: server bound IPv4 address, 4 bytes
+    match buf[1] {
+        0x5A => Ok(SocksAddr {
+            host: Host::Ipv4(Ipv4Addr::new(buf[4], buf[5], buf[6], buf[7])),
+            port: u16::from_be_bytes([buf[2], buf[3]]),
+        }),
+        0x5B => Err(Error::RequestRejected),
+        0x5C => Err(Error::IdentdUnreachable),
+        0x5D => Err(Error::IdentdMismatch),
+        _ => Err(Error::UnknownError),
+    }
+}
+
+fn get_host<R: Read>(socket: &mut R) -> Result<Host> {
     let mut buf = [0u8; 1];
     //     field 4: address type, 1 byte:
     //         0x01: IPv4 address
@@ -189,24 +349,184 @@ fn get_host(socket: &mut TcpStream) -> Result<Host> {
     }
 }
 
-fn get_port(socket: &mut TcpStream) -> Result<[u8; 2]> {
+fn get_port<R: Read>(socket: &mut R) -> Result<[u8; 2]> {
     let mut bind_port = [0u8; 2];
     //     field 6: server bound port number in a network byte order, 2 bytes
     socket.read_exact(&mut bind_port)?;
     Ok(bind_port)
 }
 
+fn get_addr<R: Read>(socket: &mut R) -> Result<SocksAddr> {
+    let host = get_host(socket)?;
+    let port = get_port(socket)?;
+    Ok(SocksAddr {
+        host,
+        port: u16::from_be_bytes(port),
+    })
+}
+
+// Username/password authentication with the credentials in the proxy URL
+// userinfo, if any.
+pub(crate) fn url_auth(proxy_addr: &Addr) -> SocksAuth {
+    match proxy_addr.username() {
+        Some(username) => {
+            SocksAuth::new_plain(&username, &proxy_addr.password().unwrap_or_default())
+        }
+        None => SocksAuth::new(),
+    }
+}
+
+fn open_socket(proxy_addr: &Addr, auth: &SocksAuth) -> Result<TcpStream> {
+    let mut socket = TcpStream::connect(proxy_addr.socket_addr()?)?;
+    initial_greeting(&mut socket, auth)?;
+    let buf = choise_communicated(&mut socket)?;
+    is_valid_socks_version(buf[0])?;
+    try_auth(&mut socket, buf[1], auth)?;
+    Ok(socket)
+}
+
+// SocksAddr is an address reported by the proxy in a reply, either the
+// address bound by the proxy or the address of a peer. The host can be
+// a domain name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocksAddr {
+    host: Host,
+    port: u16,
+}
+
+impl SocksAddr {
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // None if the host is a domain name.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self.host {
+            Host::Ipv4(ipv4) => Some(SocketAddr::new(IpAddr::V4(ipv4), self.port)),
+            Host::Ipv6(ipv6) => Some(SocketAddr::new(IpAddr::V6(ipv6), self.port)),
+            Host::Domain(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for SocksAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.host {
+            Host::Ipv6(ipv6) => write!(f, "[{}]:{}", ipv6, self.port),
+            host => write!(f, "{}:{}", host, self.port),
+        }
+    }
+}
+
+// Resolve tells where the target host name is resolved. It is chosen by
+// the scheme of the proxy URL:
+//     socks5://  the name is resolved locally and the proxy gets an IP address
+//     socks5h:// the name is sent to the proxy, which resolves it
+//     socks4://  the name is resolved locally to an IPv4 address
+//     socks4a:// the name is sent to the proxy, which resolves it
+//     socks5t:// the name is sent to the proxy; if the proxy refuses the domain
+//                name address type (reply 0x08), the connection is retried with
+//                a locally resolved IP address
+// A proxy given without a scheme resolves remotely, as socks5h.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolve {
+    Local,
+    Remote,
+    RemoteOrLocal,
+}
+
+impl Resolve {
+    pub fn from_scheme(scheme: &str) -> Self {
+        match scheme {
+            "socks5" | "socks4" => Resolve::Local,
+            "socks5t" => Resolve::RemoteOrLocal,
+            _ => Resolve::Remote,
+        }
+    }
+}
+
+// Isolation generates SOCKS5 credentials for stream isolation. Tor and
+// similar proxies never use the same circuit for connections with
+// different username and password, so connections of different sessions
+// do not share an exit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Isolation {
+    // One session for every target host.
+    PerHost,
+    // One session for every job, named by the caller.
+    PerJob(String),
+    // A new session for every connection.
+    Random,
+}
+
+impl Isolation {
+    // Returns the credentials for a connection to target. Host and job
+    // names are hashed with a random key chosen once per process, so the
+    // proxy can neither recover them nor link sessions across processes.
+    // Only username/password authentication is offered, a server that
+    // does not accept it fails the handshake instead of silently dropping
+    // the isolation.
+    pub fn auth(&self, target: &Addr) -> Result<SocksAuth> {
+        let mut hasher = Sha256::new();
+        hasher.update(isolation_key());
+        let kind = match self {
+            Isolation::PerHost => {
+                hasher.update(target.host()?.to_lowercase());
+                "host"
+            }
+            Isolation::PerJob(job) => {
+                hasher.update(job);
+                "job"
+            }
+            Isolation::Random => {
+                static COUNTER: AtomicU64 = AtomicU64::new(0);
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                hasher.update(COUNTER.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+                hasher.update(time.as_nanos().to_be_bytes());
+                "random"
+            }
+        };
+        let token: String = hasher.finalize()[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Ok(SocksAuth::new_empty().with_method(PlainAuth {
+            username: kind.as_bytes().to_vec(),
+            password: token.into_bytes(),
+        }))
+    }
+}
+
+// The key for isolation tokens, taken from the randomly seeded keys of
+// the standard library hasher.
+fn isolation_key() -> &'static [u8; 32] {
+    static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+    KEY.get_or_init(|| {
+        let mut key = [0u8; 32];
+        for (i, chunk) in key.chunks_mut(8).enumerate() {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_usize(i);
+            chunk.copy_from_slice(&hasher.finish().to_be_bytes());
+        }
+        key
+    })
+}
+
 #[derive(Debug)]
 pub struct SocksStream {
-    stream: Stream,
-    target: Addr,
-    // bind_addr: Host,
-    // bind_port: [u8; 2],
+    conn: Conn,
+    bind_addr: SocksAddr,
 }
 
 impl SocksStream {
     pub fn connect(proxy: &str, target: &str) -> Result<SocksStream> {
-        Self::handshake(proxy, &target.parse()?, &SocksAuth::new())
+        Self::connect_auth(proxy, target, &SocksAuth::new())
     }
 
     pub fn connect_plain(
@@ -215,35 +535,97 @@ impl SocksStream {
         username: &str,
         password: &str,
     ) -> Result<SocksStream> {
-        Self::handshake(
-            proxy,
-            &target.parse()?,
-            &SocksAuth::new_plain(username, password),
-        )
+        Self::connect_auth(proxy, target, &SocksAuth::new_plain(username, password))
+    }
+
+    pub fn connect_auth(proxy: &str, target: &str, auth: &SocksAuth) -> Result<SocksStream> {
+        Self::connect_auth_tls(proxy, target, auth, &TlsConfig::default())
+    }
+
+    // The TLS config applies to an https:// target. Pins and a client
+    // certificate fail with TlsRequiresTunnel for an http:// target.
+    pub fn connect_auth_tls(
+        proxy: &str,
+        target: &str,
+        auth: &SocksAuth,
+        tls: &TlsConfig,
+    ) -> Result<SocksStream> {
+        let target: Addr = target.parse()?;
+        tls.check_target(target.is_ssl())?;
+        Self::handshake(proxy, &target, auth, tls)
     }
 
-    fn handshake(proxy: &str, target: &Addr, auth: &SocksAuth) -> Result<SocksStream> {
+    // Connects through a SOCKS4 proxy, or SOCKS4a unless the proxy scheme
+    // is socks4. SOCKS4 has no authentication, only the user ID.
+    pub fn connect_v4(proxy: &str, target: &str, userid: &str) -> Result<SocksStream> {
+        Self::connect_v4_tls(proxy, target, userid, &TlsConfig::default())
+    }
+
+    pub fn connect_v4_tls(
+        proxy: &str,
+        target: &str,
+        userid: &str,
+        tls: &TlsConfig,
+    ) -> Result<SocksStream> {
         let proxy_addr: Addr = proxy.parse()?;
+        let target: Addr = target.parse()?;
+        tls.check_target(target.is_ssl())?;
+        let resolve = Resolve::from_scheme(proxy_addr.scheme());
         let mut socket = TcpStream::connect(proxy_addr.socket_addr()?)?;
-        initial_greeting(&mut socket, auth)?;
-        let buf = choise_communicated(&mut socket)?;
-        is_valid_socks_version(buf[0])?;
-        try_auth(&mut socket, buf[1], auth)?;
-        request_connection(&mut socket, target.to_vec()?)?;
+        request_connection_v4(&mut socket, &target, userid.as_bytes(), resolve)?;
+        let bind_addr = get_server_reponse_v4(&mut socket)?;
+        Self::from_socket(socket, &target, bind_addr, tls)
+    }
+
+    fn handshake(
+        proxy: &str,
+        target: &Addr,
+        auth: &SocksAuth,
+        tls: &TlsConfig,
+    ) -> Result<SocksStream> {
+        let proxy_addr: Addr = proxy.parse()?;
+        let open = |target_vec| Self::open(&proxy_addr, target, auth, target_vec, tls);
+        match Resolve::from_scheme(proxy_addr.scheme()) {
+            Resolve::Local => open(target.to_resolved_vec()?),
+            Resolve::Remote => open(target.to_vec()?),
+            Resolve::RemoteOrLocal => match open(target.to_vec()?) {
+                Err(Error::InvalidAddressType) if target.addr_type()? == 3u8 => {
+                    open(target.to_resolved_vec()?)
+                }
+                result => result,
+            },
+        }
+    }
+
+    fn open(
+        proxy_addr: &Addr,
+        target: &Addr,
+        auth: &SocksAuth,
+        target_vec: Vec<u8>,
+        tls: &TlsConfig,
+    ) -> Result<SocksStream> {
+        let mut socket = open_socket(proxy_addr, auth)?;
+        request_connection(&mut socket, Command::Connect, target_vec)?;
         get_server_reponse(&mut socket)?;
-        let _host = get_host(&mut socket)?;
-        let _port = get_port(&mut socket)?;
+        let bind_addr = get_addr(&mut socket)?;
+        Self::from_socket(socket, target, bind_addr, tls)
+    }
+
+    fn from_socket(
+        socket: TcpStream,
+        target: &Addr,
+        bind_addr: SocksAddr,
+        tls: &TlsConfig,
+    ) -> Result<SocksStream> {
         let stream = if target.is_ssl() {
-            Stream::new_tls(&target.host()?, socket)?
+            Stream::new_tls_with(&target.host()?, Stream::new_tcp(socket), tls)?
         } else {
             Stream::new_tcp(socket)
         };
 
         Ok(SocksStream {
-            stream,
-            target: target.clone(),
-            // bind_addr,
-            // bind_port,
+            conn: Conn::new(stream, target.clone()),
+            bind_addr,
         })
     }
 
@@ -255,52 +637,264 @@ impl SocksStream {
     //     Ok(stream)
     // }
 
-    pub fn get(&mut self) -> io::Result<Vec<u8>> {
-        // let mut stream = self.get_stream?;
-        let request = format!(
-            "GET {} HTTP/1.0\r\nHost: {}\r\n\r\n",
-            self.target.path(),
-            self.target.host()?
+    pub fn target(&self) -> &Addr {
+        self.conn.target()
+    }
+
+    // Details of the TLS session to an https:// target.
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        self.conn.tls_info()
+    }
+
+    // The address the proxy bound for the connection to the target, as
+    // reported in its reply. It shows the exit address of the proxy, which
+    // can differ from the address the proxy was reached at.
+    pub fn bind_addr(&self) -> &SocksAddr {
+        &self.bind_addr
+    }
+
+    pub fn send(&mut self, request: Request) -> Result<Response> {
+        self.conn.send(request)
+    }
+
+    // Sends the request and returns as soon as the response head is read.
+    // The body has to be read to the end before the next request is sent,
+    // otherwise the next request fails.
+    pub fn send_streamed(&mut self, request: Request) -> Result<StreamResponse<'_>> {
+        self.conn.send_streamed(request)
+    }
+
+    pub fn get(&mut self) -> Result<Response> {
+        self.conn.get()
+    }
+
+    pub fn post_json(&mut self, body: &str) -> Result<Response> {
+        self.conn.post_json(body)
+    }
+}
+
+// SocksListener asks the proxy with the BIND command to accept a single
+// connection from the target, for protocols where the remote side connects
+// back, like active mode FTP. The proxy replies twice: first with the
+// address it listens on, which has to be passed to the target by other
+// means, then when the target has connected.
+#[derive(Debug)]
+pub struct SocksListener {
+    socket: TcpStream,
+    target: Addr,
+    bind_addr: SocksAddr,
+}
+
+impl SocksListener {
+    pub fn bind(proxy: &str, target: &str) -> Result<SocksListener> {
+        Self::handshake(proxy, &target.parse()?, &SocksAuth::new())
+    }
+
+    pub fn bind_plain(
+        proxy: &str,
+        target: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<SocksListener> {
+        Self::handshake(
+            proxy,
+            &target.parse()?,
+            &SocksAuth::new_plain(username, password),
         )
-        .into_bytes();
-        self.stream.write_all(&request)?;
-        self.stream.flush()?;
-        let mut response = vec![];
-        self.stream.read_to_end(&mut response)?;
-        let pos = response
-            .windows(4)
-            .position(|x| x == b"\r\n\r\n")
-            .ok_or_else(|| Error::WrongHttp)?;
-        let body = &response[pos + 4..response.len()];
-        Ok(body.to_vec())
-    }
-
-    pub fn post_json(&mut self, body: &str) -> io::Result<Vec<u8>> {
-        let body = if !body.is_empty() {
-            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
-        } else {
-            String::new()
+    }
+
+    pub fn bind_auth(proxy: &str, target: &str, auth: &SocksAuth) -> Result<SocksListener> {
+        Self::handshake(proxy, &target.parse()?, auth)
+    }
+
+    fn handshake(proxy: &str, target: &Addr, auth: &SocksAuth) -> Result<SocksListener> {
+        let proxy_addr: Addr = proxy.parse()?;
+        let target_vec = match Resolve::from_scheme(proxy_addr.scheme()) {
+            Resolve::Local => target.to_resolved_vec()?,
+            _ => target.to_vec()?,
         };
-        let request = format!(
-            "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\n{}\r\n",
-            self.target.path(),
-            self.target.host()?,
-            body
-        )
-        .into_bytes();
-        self.stream.write_all(&request)?;
-        self.stream.flush()?;
-        let mut response = vec![];
-        self.stream.read_to_end(&mut response)?;
-        let pos = response
-            .windows(4)
-            .position(|x| x == b"\r\n\r\n")
-            .ok_or_else(|| Error::WrongHttp)?;
-        let body = &response[pos + 4..response.len()];
-        Ok(body.to_vec())
+        let mut socket = open_socket(&proxy_addr, auth)?;
+        request_connection(&mut socket, Command::Bind, target_vec)?;
+        get_server_reponse(&mut socket)?;
+        let bind_addr = get_addr(&mut socket)?;
+        Ok(SocksListener {
+            socket,
+            target: target.clone(),
+            bind_addr,
+        })
+    }
+
+    // The address the proxy listens on for the incoming connection.
+    pub fn bind_addr(&self) -> &SocksAddr {
+        &self.bind_addr
+    }
+
+    // Waits for the second reply and returns the stream to the peer that
+    // connected together with its address.
+    pub fn accept(self) -> Result<(SocksStream, SocksAddr)> {
+        self.accept_tls(&TlsConfig::default())
+    }
+
+    // The TLS config applies when the target is https://.
+    pub fn accept_tls(mut self, tls: &TlsConfig) -> Result<(SocksStream, SocksAddr)> {
+        tls.check_target(self.target.is_ssl())?;
+        get_server_reponse(&mut self.socket)?;
+        let peer_addr = get_addr(&mut self.socket)?;
+        let stream = SocksStream::from_socket(self.socket, &self.target, self.bind_addr, tls)?;
+        Ok((stream, peer_addr))
     }
 }
 
+// SocksDatagram relays UDP datagrams through the proxy after the UDP
+// ASSOCIATE command. The association lasts as long as the control TCP
+// connection, so it is kept open with the socket. Every datagram to and
+// from the relay starts with a header:
+//     field 1: reserved, must be 0x0000, 2 bytes
+//     field 2: fragment number, 1 byte (0x00 for a standalone datagram)
+//     field 3: address type, 1 byte
+//     field 4: destination address, as in the connection request
+//     field 5: destination port in a network byte order, 2 bytes
+//     field 6: user data
+// Fragmentation is not supported, fragments are dropped.
+#[derive(Debug)]
+pub struct SocksDatagram {
+    socket: UdpSocket,
+    relay_addr: SocketAddr,
+    resolve: Resolve,
+    _control: TcpStream,
+}
+
+impl SocksDatagram {
+    // Binds the local UDP socket to addr, e.g. "0.0.0.0:0", and asks the
+    // proxy to relay datagrams from it.
+    pub fn bind(proxy: &str, addr: &str) -> Result<SocksDatagram> {
+        Self::associate(proxy, addr, &SocksAuth::new())
+    }
+
+    pub fn bind_plain(
+        proxy: &str,
+        addr: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<SocksDatagram> {
+        Self::associate(proxy, addr, &SocksAuth::new_plain(username, password))
+    }
+
+    pub fn bind_auth(proxy: &str, addr: &str, auth: &SocksAuth) -> Result<SocksDatagram> {
+        Self::associate(proxy, addr, auth)
+    }
+
+    fn associate(proxy: &str, addr: &str, auth: &SocksAuth) -> Result<SocksDatagram> {
+        let proxy_addr: Addr = proxy.parse()?;
+        let socket = UdpSocket::bind(addr)?;
+        let mut control = open_socket(&proxy_addr, auth)?;
+        // The address the client sends datagrams from, or zeros if unknown.
+        let local_addr = socket.local_addr()?;
+        let mut local_vec = match local_addr.ip() {
+            IpAddr::V4(ipv4) => [&[1u8][..], &ipv4.octets()].concat(),
+            IpAddr::V6(ipv6) => [&[4u8][..], &ipv6.octets()].concat(),
+        };
+        local_vec.extend_from_slice(&local_addr.port().to_be_bytes());
+        request_connection(&mut control, Command::UdpAssociate, local_vec)?;
+        get_server_reponse(&mut control)?;
+        let bind_addr = get_addr(&mut control)?;
+        // A relay on an unspecified address is reached at the proxy host.
+        let relay_addr = match bind_addr.socket_addr() {
+            Some(relay_addr) if relay_addr.ip().is_unspecified() => {
+                SocketAddr::new(control.peer_addr()?.ip(), relay_addr.port())
+            }
+            Some(relay_addr) => relay_addr,
+            None => (bind_addr.host.to_string().as_str(), bind_addr.port)
+                .to_socket_addrs()?
+                .next()
+                .ok_or(Error::EmptyVec)?,
+        };
+        Ok(SocksDatagram {
+            socket,
+            relay_addr,
+            resolve: Resolve::from_scheme(proxy_addr.scheme()),
+            _control: control,
+        })
+    }
+
+    // The address of the proxy UDP relay.
+    pub fn relay_addr(&self) -> SocketAddr {
+        self.relay_addr
+    }
+
+    pub fn send_to(&self, buf: &[u8], target: &str) -> Result<usize> {
+        let target: Addr = target.parse()?;
+        let mut packet = vec![0u8, 0u8, 0u8];
+        match self.resolve {
+            Resolve::Local => packet.append(&mut target.to_resolved_vec()?),
+            _ => packet.append(&mut target.to_vec()?),
+        }
+        let header_len = packet.len();
+        packet.extend_from_slice(buf);
+        let len = self.socket.send_to(&packet, self.relay_addr)?;
+        Ok(len.saturating_sub(header_len))
+    }
+
+    // Datagrams that do not come from the relay, fragments and datagrams
+    // with a malformed header are skipped, only socket errors are returned.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocksAddr)> {
+        // The longest header has a 255 bytes domain name.
+        let mut packet = vec![0u8; buf.len() + 262];
+        loop {
+            let (len, from) = self.socket.recv_from(&mut packet)?;
+            if from != self.relay_addr || len < 4 || packet[..3] != [0u8, 0u8, 0u8] {
+                continue;
+            }
+            let mut data = &packet[3..len];
+            let addr = match get_addr(&mut data) {
+                Ok(addr) => addr,
+                Err(_) => continue,
+            };
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            return Ok((len, addr));
+        }
+    }
+}
+
+// Tor extends SOCKS5 with the commands RESOLVE (0xF0) and RESOLVE_PTR
+// (0xF1), which resolve a name or an address through the proxy without
+// opening a stream. The answer comes in the address field of the reply.
+// Credentials in the proxy URL userinfo are used for authentication.
+pub fn resolve(proxy: &str, name: &str) -> Result<IpAddr> {
+    if name.is_empty() || name.len() > 255 {
+        return Err(Error::InvalidHost);
+    }
+    let mut target = vec![3u8, name.len() as u8];
+    target.extend_from_slice(name.as_bytes());
+    target.extend_from_slice(&[0u8, 0u8]);
+    match tor_request(proxy, Command::Resolve, target)? {
+        Host::Ipv4(ipv4) => Ok(IpAddr::V4(ipv4)),
+        Host::Ipv6(ipv6) => Ok(IpAddr::V6(ipv6)),
+        Host::Domain(_) => Err(Error::InvalidAddressType),
+    }
+}
+
+pub fn resolve_ptr(proxy: &str, ip: IpAddr) -> Result<String> {
+    let mut target = match ip {
+        IpAddr::V4(ipv4) => [&[1u8][..], &ipv4.octets()].concat(),
+        IpAddr::V6(ipv6) => [&[4u8][..], &ipv6.octets()].concat(),
+    };
+    target.extend_from_slice(&[0u8, 0u8]);
+    match tor_request(proxy, Command::ResolvePtr, target)? {
+        Host::Domain(domain) => Ok(domain),
+        _ => Err(Error::InvalidAddressType),
+    }
+}
+
+fn tor_request(proxy: &str, command: Command, target: Vec<u8>) -> Result<Host> {
+    let proxy_addr: Addr = proxy.parse()?;
+    let mut socket = open_socket(&proxy_addr, &url_auth(&proxy_addr))?;
+    request_connection(&mut socket, command, target)?;
+    get_server_reponse(&mut socket)?;
+    Ok(get_addr(&mut socket)?.host)
+}
+
 // pub fn get(proxy: &str, target: &str) -> io::Result<Vec<u8>> {
 //     let mut stream = SocksStream::connect(proxy, target)?;
 //     let request = format!(
@@ -316,7 +910,7 @@ impl SocksStream {
 //     let pos = response
 //         .windows(4)
 //         .position(|x| x == b"\r\n\r\n")
-//         .ok_or_else(|| Error::WrongHttp)?;
+//         .ok_or(Error::WrongHttp)?;
 //     let body = &response[pos + 4..response.len()];
 //     Ok(body.to_vec())
 // }
@@ -342,7 +936,7 @@ impl SocksStream {
 //     let pos = response
 //         .windows(4)
 //         .position(|x| x == b"\r\n\r\n")
-//         .ok_or_else(|| Error::WrongHttp)?;
+//         .ok_or(Error::WrongHttp)?;
 //     let body = &response[pos + 4..response.len()];
 //     Ok(body.to_vec())
 // }
@@ -366,6 +960,8 @@ impl SocksStream {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::BufRead;
+    use std::thread;
 
     lazy_static! {
         static ref IP: String = crate::my_ip();
@@ -374,8 +970,8 @@ mod tests {
     #[test]
     fn socks() {
         let mut client = SocksStream::connect("127.0.0.1:5959", "https://api.ipify.org").unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
 
@@ -384,8 +980,8 @@ mod tests {
         let mut client =
             SocksStream::connect_plain("127.0.0.1:5757", "https://api.ipify.org", "test", "tset")
                 .unwrap();
-        let body = client.get().unwrap();
-        let txt = String::from_utf8_lossy(&body);
+        let response = client.get().unwrap();
+        let txt = String::from_utf8_lossy(response.body());
         assert!(txt.contains(crate::tests::IP.as_str()));
     }
 
@@ -395,4 +991,343 @@ mod tests {
             SocksStream::connect_plain("127.0.0.1:5757", "https://api.ipify.org", "test", "test");
         assert!(client.is_err());
     }
+
+    type Requests = Vec<(u8, Vec<u8>)>;
+
+    // Reads a greeting of len bytes and answers with the chosen method.
+    fn greet(stream: &mut TcpStream, len: usize, method: u8) -> Vec<u8> {
+        let mut greeting = vec![0u8; len];
+        stream.read_exact(&mut greeting).unwrap();
+        stream.write_all(&[5, method]).unwrap();
+        greeting
+    }
+
+    // Accepts a connection for every reply code and returns the address
+    // type and address of each connection request.
+    fn mock_proxy(replies: Vec<u8>) -> (String, thread::JoinHandle<Requests>) {
+        let mut replies = replies.into_iter();
+        crate::mock_server(replies.len(), move |mut stream| {
+            greet(&mut stream, 3, 0);
+            let mut head = [0u8; 4];
+            stream.read_exact(&mut head).unwrap();
+            let len = match head[3] {
+                1 => 4,
+                4 => 16,
+                _ => {
+                    let mut len = [0u8; 1];
+                    stream.read_exact(&mut len).unwrap();
+                    len[0] as usize
+                }
+            };
+            let mut host = vec![0u8; len + 2];
+            stream.read_exact(&mut host).unwrap();
+            host.truncate(len);
+            let reply = replies.next().unwrap();
+            stream
+                .write_all(&[5, reply, 0, 1, 127, 0, 0, 1, 0, 80])
+                .unwrap();
+            (head[3], host)
+        })
+    }
+
+    #[test]
+    fn socks4() {
+        for (proxy, target, expected) in &[
+            (
+                "socks4",
+                "http://127.0.0.1:8080",
+                &b"\x04\x01\x1f\x90\x7f\x00\x00\x01id\x00"[..],
+            ),
+            (
+                "socks4a",
+                "http://example.org",
+                &b"\x04\x01\x00\x50\x00\x00\x00\x01id\x00example.org\x00"[..],
+            ),
+        ] {
+            let len = expected.len();
+            let mut replies = vec![0x5Au8, 0x5B, 0x5C, 0x5D].into_iter();
+            let (addr, handle) = crate::mock_server(4, move |mut stream| {
+                let mut request = vec![0u8; len];
+                stream.read_exact(&mut request).unwrap();
+                let reply = replies.next().unwrap();
+                stream
+                    .write_all(&[0, reply, 0x1f, 0x90, 10, 0, 0, 1])
+                    .unwrap();
+                request
+            });
+            let proxy = format!("{}://{}", proxy, addr);
+            let stream = SocksStream::connect_v4(&proxy, target, "id").unwrap();
+            assert_eq!(
+                stream.bind_addr().socket_addr(),
+                Some("10.0.0.1:8080".parse().unwrap())
+            );
+            for expected_err in &[
+                Error::RequestRejected,
+                Error::IdentdUnreachable,
+                Error::IdentdMismatch,
+            ] {
+                match SocksStream::connect_v4(&proxy, target, "id") {
+                    Err(err) => assert_eq!(err.to_string(), expected_err.to_string()),
+                    Ok(_) => panic!("expected {}", expected_err),
+                }
+            }
+            for request in handle.join().unwrap() {
+                assert_eq!(&request[..], *expected);
+            }
+        }
+    }
+
+    struct TokenAuth;
+
+    impl Authenticator for TokenAuth {
+        fn method(&self) -> u8 {
+            0x80
+        }
+
+        fn authenticate(&self, socket: &mut TcpStream) -> Result<()> {
+            socket.write_all(b"token")?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn socks_auth_methods() {
+        for (auth, choice, expected) in [
+            (
+                SocksAuth::new_plain("u", "p"),
+                0u8,
+                &b"\x05\x02\x02\x00"[..],
+            ),
+            (
+                SocksAuth::new().with_method(TokenAuth),
+                0x80,
+                &b"\x05\x02\x00\x80token"[..],
+            ),
+        ] {
+            let len = expected.len();
+            let (addr, handle) = crate::mock_server(1, move |mut stream| {
+                let mut greeting = greet(&mut stream, 4, choice);
+                greeting.resize(len, 0);
+                stream.read_exact(&mut greeting[4..]).unwrap();
+                let mut request = [0u8; 10];
+                stream.read_exact(&mut request).unwrap();
+                stream
+                    .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80])
+                    .unwrap();
+                greeting
+            });
+            let proxy = format!("socks5h://{}", addr);
+            SocksStream::connect_auth(&proxy, "127.0.0.1:80", &auth).unwrap();
+            assert_eq!(&handle.join().unwrap()[0][..], expected);
+        }
+    }
+
+    #[test]
+    fn socks_tor_resolve() {
+        let mut replies = vec![
+            &[5u8, 0, 0, 1, 93, 184, 216, 34, 0, 0][..],
+            &b"\x05\x00\x00\x03\x0bexample.org\x00\x00"[..],
+        ]
+        .into_iter();
+        let (addr, handle) = crate::mock_server(2, move |mut stream| {
+            greet(&mut stream, 3, 0);
+            let mut request = vec![0u8; 4];
+            stream.read_exact(&mut request).unwrap();
+            let len = if request[1] == 0xF0 { 12 + 2 } else { 4 + 2 };
+            request.resize(4 + len, 0);
+            stream.read_exact(&mut request[4..]).unwrap();
+            stream.write_all(replies.next().unwrap()).unwrap();
+            request
+        });
+        let proxy = format!("socks5h://{}", addr);
+        assert_eq!(
+            resolve(&proxy, "example.org").unwrap(),
+            "93.184.216.34".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            resolve_ptr(&proxy, "93.184.216.34".parse().unwrap()).unwrap(),
+            "example.org"
+        );
+        let requests = handle.join().unwrap();
+        assert_eq!(
+            requests[0],
+            b"\x05\xf0\x00\x03\x0bexample.org\x00\x00".to_vec()
+        );
+        assert_eq!(requests[1], vec![5, 0xF1, 0, 1, 93, 184, 216, 34, 0, 0]);
+    }
+
+    // Returns the username/password packets sent for each target.
+    fn isolation_auth(isolation: &Isolation, targets: &[&str]) -> Vec<Vec<u8>> {
+        let (addr, handle) = crate::mock_server(targets.len(), |mut stream| {
+            assert_eq!(greet(&mut stream, 3, 2), vec![5, 1, 2]);
+            let mut packet = vec![0u8; 2];
+            stream.read_exact(&mut packet).unwrap();
+            let len = packet[1] as usize;
+            packet.resize(2 + len + 1, 0);
+            stream.read_exact(&mut packet[2..]).unwrap();
+            let len = packet[2 + len] as usize;
+            let start = packet.len();
+            packet.resize(start + len, 0);
+            stream.read_exact(&mut packet[start..]).unwrap();
+            stream.write_all(&[1, 1]).unwrap();
+            packet
+        });
+        let proxy = format!("socks5h://{}", addr);
+        for target in targets {
+            let auth = isolation.auth(&target.parse().unwrap()).unwrap();
+            assert!(SocksStream::connect_auth(&proxy, target, &auth).is_err());
+        }
+        handle.join().unwrap()
+    }
+
+    #[test]
+    fn socks_isolation() {
+        let targets = ["http://a.example", "http://A.example/x", "http://b.example"];
+        let packets = isolation_auth(&Isolation::PerHost, &targets);
+        assert!(packets[0].starts_with(b"\x01\x04host\x10"));
+        assert_eq!(packets[0], packets[1]);
+        assert_ne!(packets[0], packets[2]);
+
+        let packets = isolation_auth(&Isolation::PerJob("job".to_string()), &targets);
+        assert!(packets[0].starts_with(b"\x01\x03job\x10"));
+        assert!(packets.iter().all(|packet| packet == &packets[0]));
+        let other = isolation_auth(&Isolation::PerJob("other".to_string()), &targets[..1]);
+        assert_ne!(packets[0], other[0]);
+
+        let packets = isolation_auth(&Isolation::Random, &targets[..2]);
+        assert_ne!(packets[0], packets[1]);
+    }
+
+    #[test]
+    fn socks_target_tls() {
+        let tls = TlsConfig::new().pin_spki_sha256([0u8; 32]);
+        let results = vec![
+            SocksStream::connect_auth_tls(
+                "socks5://127.0.0.1:1",
+                "http://example.org/",
+                &SocksAuth::new(),
+                &tls,
+            ),
+            SocksStream::connect_v4_tls("socks4://127.0.0.1:1", "http://example.org/", "", &tls),
+        ];
+        for result in results {
+            match result {
+                Err(Error::TlsRequiresTunnel) => (),
+                result => panic!("unexpected result {:?}", result.err()),
+            }
+        }
+    }
+
+    #[test]
+    fn socks_isolation_no_auth() {
+        let (addr, handle) = crate::mock_server(1, |mut stream| greet(&mut stream, 3, 0));
+        let auth = Isolation::PerHost
+            .auth(&"http://a.example".parse().unwrap())
+            .unwrap();
+        let proxy = format!("socks5h://{}", addr);
+        match SocksStream::connect_auth(&proxy, "http://a.example", &auth) {
+            Err(Error::InvalidAuthMethod) => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn socks_bind() {
+        let (addr, handle) = crate::mock_server(1, |mut stream| {
+            greet(&mut stream, 3, 0);
+            let mut request = [0u8; 10];
+            stream.read_exact(&mut request).unwrap();
+            stream
+                .write_all(&[5, 0, 0, 1, 192, 0, 2, 1, 0x13, 0x88])
+                .unwrap();
+            stream
+                .write_all(&[5, 0, 0, 1, 198, 51, 100, 7, 0, 20])
+                .unwrap();
+            stream.write_all(b"220 ready\r\n").unwrap();
+            request
+        });
+        let listener =
+            SocksListener::bind(&format!("socks5h://{}", addr), "198.51.100.7:21").unwrap();
+        assert_eq!(listener.bind_addr().to_string(), "192.0.2.1:5000");
+        let (mut stream, peer_addr) = listener.accept().unwrap();
+        assert_eq!(
+            peer_addr.socket_addr(),
+            Some("198.51.100.7:20".parse().unwrap())
+        );
+        let mut line = String::new();
+        stream.conn.get_mut().read_line(&mut line).unwrap();
+        assert_eq!(line, "220 ready\r\n");
+        assert_eq!(
+            handle.join().unwrap()[0],
+            [5, 2, 0, 1, 198, 51, 100, 7, 0, 21]
+        );
+    }
+
+    #[test]
+    fn socks_udp() {
+        let relay = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let relay_port = relay.local_addr().unwrap().port().to_be_bytes();
+        let (addr, handle) = crate::mock_server(1, move |mut stream| {
+            greet(&mut stream, 3, 0);
+            let mut request = [0u8; 10];
+            stream.read_exact(&mut request).unwrap();
+            stream
+                .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, relay_port[0], relay_port[1]])
+                .unwrap();
+            let mut buf = [0u8; 64];
+            let (len, client) = relay.recv_from(&mut buf).unwrap();
+            let datagram = buf[..len].to_vec();
+            // Garbage and fragments are dropped by the client.
+            for garbage in &[
+                &[0u8, 0, 0, 9, 1, 2, 3][..],
+                &[0, 0, 0, 1, 192, 0][..],
+                &[0, 0, 0, 3, 200, b'a'][..],
+                &[0, 0, 1, 1, 192, 0, 2, 9, 0, 53, b'x'][..],
+            ] {
+                relay.send_to(garbage, client).unwrap();
+            }
+            relay
+                .send_to(&[0, 0, 0, 1, 192, 0, 2, 9, 0, 53, b'o', b'k'], client)
+                .unwrap();
+            (request, datagram)
+        });
+        let datagram = SocksDatagram::bind(&format!("socks5h://{}", addr), "127.0.0.1:0").unwrap();
+        assert_eq!(
+            datagram.relay_addr().ip(),
+            addr.parse::<SocketAddr>().unwrap().ip()
+        );
+        assert_eq!(datagram.send_to(b"ping", "192.0.2.9:53").unwrap(), 4);
+        let mut buf = [0u8; 16];
+        let (len, from) = datagram.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"ok");
+        assert_eq!(from.to_string(), "192.0.2.9:53");
+        let (request, sent) = handle.join().unwrap().remove(0);
+        assert_eq!(request[..4], [5, 3, 0, 1]);
+        assert_eq!(sent, b"\x00\x00\x00\x01\xc0\x00\x02\x09\x00\x35ping");
+    }
+
+    #[test]
+    fn socks_resolve() {
+        let (addr, handle) = mock_proxy(vec![0]);
+        let stream =
+            SocksStream::connect(&format!("socks5h://{}", addr), "http://localhost").unwrap();
+        assert_eq!(handle.join().unwrap(), vec![(3, b"localhost".to_vec())]);
+        assert_eq!(
+            stream.bind_addr().host(),
+            &Host::<String>::Ipv4(Ipv4Addr::LOCALHOST)
+        );
+        assert_eq!(stream.bind_addr().port(), 80);
+
+        let (addr, handle) = mock_proxy(vec![0]);
+        SocksStream::connect(&format!("socks5://{}", addr), "http://localhost").unwrap();
+        let requests = handle.join().unwrap();
+        assert!(requests[0].0 == 1 || requests[0].0 == 4);
+
+        let (addr, handle) = mock_proxy(vec![8, 0]);
+        SocksStream::connect(&format!("socks5t://{}", addr), "http://localhost").unwrap();
+        let requests = handle.join().unwrap();
+        assert_eq!(requests[0], (3, b"localhost".to_vec()));
+        assert!(requests[1].0 == 1 || requests[1].0 == 4);
+    }
 }
@@ -1,14 +1,14 @@
 use std::io::{self, Read, Write};
 use std::net::TcpStream;
 
-use native_tls::{TlsConnector, TlsStream};
-
-use crate::error::{Error, Result};
+use crate::error::Result;
+use crate::tls::{TlsConfig, TlsInfo, TlsStream};
 
 #[derive(Debug)]
 pub enum Stream {
     Tcp(TcpStream),
-    Tls(Box<TlsStream<TcpStream>>),
+    // Tls may run over another Stream, such as a TLS session to a proxy.
+    Tls(Box<TlsStream>),
 }
 
 impl Stream {
@@ -16,11 +16,20 @@ impl Stream {
         Stream::Tcp(stream)
     }
 
-    pub fn new_tls(domain: &str, stream: TcpStream) -> Result<Self> {
-        let builder = TlsConnector::new().map_err(Error::TlsConnector)?;
-        Ok(Stream::Tls(Box::new(
-            builder.connect(domain, stream).map_err(Error::NativeTls)?,
-        )))
+    pub fn new_tls(domain: &str, stream: Stream) -> Result<Self> {
+        Stream::new_tls_with(domain, stream, &TlsConfig::default())
+    }
+
+    pub fn new_tls_with(domain: &str, stream: Stream, config: &TlsConfig) -> Result<Self> {
+        Ok(Stream::Tls(Box::new(config.connect(domain, stream)?)))
+    }
+
+    // Details of the TLS session, None for a plain TCP stream.
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        match self {
+            Stream::Tcp(_) => None,
+            Stream::Tls(stream) => Some(stream.tls_info()),
+        }
     }
 }
 